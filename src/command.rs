@@ -0,0 +1,166 @@
+/// Meta-commands handled by rpsql itself instead of being sent to the server.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// `\s [file]` : display the history, or save it to `file`
+    History(Option<String>),
+    /// `\history delete <pattern>` : remove entries matching `pattern`
+    HistoryDelete(String),
+    /// `\history clear` : remove every entry
+    HistoryClear,
+    /// `\e [file] [line]` : edit a file, or the last query, in an external editor
+    Edit(Option<String>, Option<usize>),
+    /// `\ef [function [line]]` : edit a function definition
+    EditFunction(Option<String>, Option<usize>),
+    /// `\ev [view [line]]` : edit a view definition
+    EditView(Option<String>, Option<usize>),
+    /// `\bindings` : list the key bindings
+    Bindings,
+    /// `\pset option [value]` : change a setting for the session
+    Pset(String, Option<String>),
+    /// `\results` : list the kept results
+    Results,
+    /// `\results <id>` : show a kept result again
+    ResultsShow(usize),
+    /// `\results export <id> <file> [format]` : write a kept result to a file
+    ResultsExport(usize, String, Option<String>),
+    /// `\results compare <id> <id>` : tell how two kept results differ
+    ResultsCompare(usize, usize),
+    /// `\results diff <id> <id> [key,...]` : show two kept results side by side in the pager
+    ResultsDiff(usize, usize, Vec<String>),
+}
+
+impl Command {
+    /// Returns `Ok(None)` if `input` is not a meta-command known by rpsql.
+    pub fn parse(input: &str) -> Result<Option<Command>, String> {
+        let input = input.trim();
+        if !input.starts_with('\\') {
+            return Ok(None);
+        }
+        let mut words = input[1..].splitn(2, char::is_whitespace);
+        let name = words.next().unwrap_or_default();
+        let args = words.next().unwrap_or_default().trim();
+
+        match name {
+            "s" => Ok(Some(Command::History(_optional_arg(args)))),
+            "history" => _parse_history(args).map(Some),
+            // Like psql, a lone number is a line number in the last query
+            "e" if !args.is_empty() && args.chars().all(|c| c.is_ascii_digit()) => {
+                args.parse::<usize>()
+                    .map(|l| Some(Command::Edit(None, Some(l))))
+                    .map_err(|e| format!("Invalid line number {} : {}", args, e))
+            },
+            "e" => _parse_edit(args).map(|(file, line)| Some(Command::Edit(file, line))),
+            "ef" => _parse_edit(args).map(|(function, line)| Some(Command::EditFunction(function, line))),
+            "ev" => _parse_edit(args).map(|(view, line)| Some(Command::EditView(view, line))),
+            "bindings" => Ok(Some(Command::Bindings)),
+            "pset" => _parse_pset(args).map(Some),
+            "results" => _parse_results(args).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+fn _optional_arg(args: &str) -> Option<String> {
+    if args.is_empty() {
+        None
+    } else {
+        Some(args.to_string())
+    }
+}
+
+/// `[name [line]]`, the name can contain spaces (function signatures)
+fn _parse_edit(args: &str) -> Result<(Option<String>, Option<usize>), String> {
+    if args.is_empty() {
+        return Ok((None, None));
+    }
+    match args.rsplitn(2, char::is_whitespace).collect::<Vec<&str>>()[..] {
+        [line, name] if line.chars().all(|c| c.is_ascii_digit()) => {
+            line.parse::<usize>()
+                .map(|l| (Some(name.trim().to_string()), Some(l)))
+                .map_err(|e| format!("Invalid line number {} : {}", line, e))
+        },
+        _ => Ok((Some(args.to_string()), None)),
+    }
+}
+
+fn _parse_history(args: &str) -> Result<Command, String> {
+    let mut words = args.splitn(2, char::is_whitespace);
+    match (words.next().unwrap_or_default(), words.next().map(str::trim)) {
+        ("delete", Some(pattern)) if !pattern.is_empty() => Ok(Command::HistoryDelete(pattern.to_string())),
+        ("clear", None) => Ok(Command::HistoryClear),
+        _ => Err("Usage : \\history delete <pattern> | \\history clear".to_string()),
+    }
+}
+
+fn _parse_pset(args: &str) -> Result<Command, String> {
+    let mut words = args.splitn(2, char::is_whitespace);
+    match (words.next().unwrap_or_default(), words.next().map(str::trim)) {
+        ("", _) => Err("Usage : \\pset pager [on|off|always]".to_string()),
+        (option, value) => Ok(Command::Pset(option.to_string(), value.and_then(_optional_arg))),
+    }
+}
+
+fn _parse_results(args: &str) -> Result<Command, String> {
+    let words: Vec<&str> = args.split_whitespace().collect();
+    let id = |word: &str| word.parse::<usize>().map_err(|e| format!("Invalid result id {} : {}", word, e));
+    match words[..] {
+        [] => Ok(Command::Results),
+        ["export", result, file] => Ok(Command::ResultsExport(id(result)?, file.to_string(), None)),
+        ["export", result, file, format] => Ok(Command::ResultsExport(id(result)?, file.to_string(), Some(format.to_string()))),
+        ["compare", a, b] => Ok(Command::ResultsCompare(id(a)?, id(b)?)),
+        ["diff", a, b] => Ok(Command::ResultsDiff(id(a)?, id(b)?, Vec::new())),
+        ["diff", a, b, keys] => Ok(Command::ResultsDiff(id(a)?, id(b)?, keys.split(',').map(String::from).collect())),
+        [result] => Ok(Command::ResultsShow(id(result)?)),
+        _ => Err("Usage : \\results [<id> | export <id> <file> [format] | compare <id> <id> | diff <id> <id> [key,...]]".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn not_a_command() {
+        assert_eq!(Command::parse("select 1"), Ok(None));
+        assert_eq!(Command::parse("\\d"), Ok(None));
+    }
+
+    #[test]
+    fn history() {
+        assert_eq!(Command::parse("\\s"), Ok(Some(Command::History(None))));
+        assert_eq!(Command::parse("\\s /tmp/history.sql "), Ok(Some(Command::History(Some("/tmp/history.sql".to_string())))));
+        assert_eq!(Command::parse("\\history delete  *drop*"), Ok(Some(Command::HistoryDelete("*drop*".to_string()))));
+        assert_eq!(Command::parse("\\history clear"), Ok(Some(Command::HistoryClear)));
+        assert!(Command::parse("\\history delete").is_err());
+        assert!(Command::parse("\\history").is_err());
+        assert_eq!(Command::parse("\\bindings"), Ok(Some(Command::Bindings)));
+    }
+
+    #[test]
+    fn edit() {
+        assert_eq!(Command::parse("\\e"), Ok(Some(Command::Edit(None, None))));
+        assert_eq!(Command::parse("\\e 3"), Ok(Some(Command::Edit(None, Some(3)))));
+        assert_eq!(Command::parse("\\e query.sql"), Ok(Some(Command::Edit(Some("query.sql".to_string()), None))));
+        assert_eq!(Command::parse("\\e query.sql 12"), Ok(Some(Command::Edit(Some("query.sql".to_string()), Some(12)))));
+        assert_eq!(Command::parse("\\ef add(int, int) 3"), Ok(Some(Command::EditFunction(Some("add(int, int)".to_string()), Some(3)))));
+        assert_eq!(Command::parse("\\ev my_view"), Ok(Some(Command::EditView(Some("my_view".to_string()), None))));
+    }
+
+    #[test]
+    fn pset() {
+        assert_eq!(Command::parse("\\pset pager always"), Ok(Some(Command::Pset("pager".to_string(), Some("always".to_string())))));
+        assert_eq!(Command::parse("\\pset pager"), Ok(Some(Command::Pset("pager".to_string(), None))));
+        assert!(Command::parse("\\pset").is_err());
+    }
+
+    #[test]
+    fn results() {
+        assert_eq!(Command::parse("\\results"), Ok(Some(Command::Results)));
+        assert_eq!(Command::parse("\\results 3"), Ok(Some(Command::ResultsShow(3))));
+        assert_eq!(Command::parse("\\results export 3 out.csv"), Ok(Some(Command::ResultsExport(3, "out.csv".to_string(), None))));
+        assert_eq!(Command::parse("\\results compare 1 2"), Ok(Some(Command::ResultsCompare(1, 2))));
+        assert_eq!(Command::parse("\\results diff 1 2 id,day"), Ok(Some(Command::ResultsDiff(1, 2, vec!["id".to_string(), "day".to_string()]))));
+        assert!(Command::parse("\\results compare 1").is_err());
+        assert!(Command::parse("\\results last").is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Stdout, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use termion::raw::RawTerminal;
+use crate::ui::event::{BRACKETED_PASTE_ON, BRACKETED_PASTE_OFF};
+
+const DEFAULT_EDITOR: &str = "vi";
+/// Names tried for a temporary file before giving up
+const TEMP_FILE_ATTEMPTS: usize = 16;
+
+/// Opens `path` in `$VISUAL` or `$EDITOR` (`vi` if none is set), at `line` if given, and returns its new contents
+pub fn edit_file(stdout: &RawTerminal<Stdout>, path: &Path, line: Option<usize>) -> io::Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string());
+    let mut words = editor.split_whitespace();
+    let mut command = process::Command::new(words.next().unwrap());
+    command.args(words);
+    if let Some(line) = line {
+        command.arg(format!("+{}", line));
+    }
+    command.arg(path);
+
+    // The editor may not understand bracketed paste
+    print!("{}", BRACKETED_PASTE_OFF);
+    io::stdout().flush()?;
+    stdout.suspend_raw_mode()?;
+    let status = command.status();
+    stdout.activate_raw_mode()?;
+    print!("{}", BRACKETED_PASTE_ON);
+    let status = status.map_err(|e| io::Error::new(e.kind(), format!("could not run editor {} : {}", editor, e)))?;
+    if !status.success() {
+        return Err(io::Error::other(format!("editor {} exited with {}", editor, status)));
+    }
+    Ok(fs::read_to_string(path)?.trim_end().to_string())
+}
+
+/// Same as `edit_file`, on a temporary file filled with `contents`
+pub fn edit_text(stdout: &RawTerminal<Stdout>, contents: &str, line: Option<usize>) -> io::Result<String> {
+    let path = _create_temp_file(contents)?;
+    let res = edit_file(stdout, &path, line);
+    let _ = fs::remove_file(&path);
+    res
+}
+
+/// A new file only readable by the user, under a random name : the query can contain passwords,
+/// and an existing file or symlink with that name is never reused
+fn _create_temp_file(contents: &str) -> io::Result<PathBuf> {
+    for _ in 0..TEMP_FILE_ATTEMPTS {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(process::id());
+        let path = env::temp_dir().join(format!("rpsql.edit.{:016x}.sql", hasher.finish()));
+        let file = fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&path);
+        match file {
+            Ok(mut file) => {
+                if let Err(e) = file.write_all(format!("{}\n", contents).as_bytes()) {
+                    let _ = fs::remove_file(&path);
+                    return Err(e);
+                }
+                return Ok(path);
+            },
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, "could not create a temporary file"))
+}
+
+/// Like in psql, an edited query ending with a semicolon is executed right away
+/// instead of being loaded in the input line
+pub fn is_terminated(query: &str) -> bool {
+    query.trim_end().ends_with(';')
+}
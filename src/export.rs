@@ -0,0 +1,212 @@
+use postgres_types::Type;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use crate::sql::{self, QueryResult};
+
+/// Table named in INSERT statements, the result does not tell where its rows come from
+const INSERT_TABLE: &str = "result";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+    Insert,
+    Markdown,
+}
+
+impl Format {
+    pub const ALL: [Format; 4] = [Format::Csv, Format::Json, Format::Insert, Format::Markdown];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Json => "json",
+            Format::Insert => "insert",
+            Format::Markdown => "markdown",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_str() {
+            "sql" => Some(Format::Insert),
+            "md" => Some(Format::Markdown),
+            name => Format::ALL.iter().copied().find(|f| f.name() == name),
+        }
+    }
+
+    /// From the extension of a file name
+    pub fn from_path(path: &str) -> Option<Format> {
+        match path.rsplit_once('.')?.1.to_lowercase().as_str() {
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "sql" => Some(Format::Insert),
+            "md" | "markdown" => Some(Format::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// `rows` of `result`, in their order : CSV with a header, a JSON array of objects, one INSERT per row
+/// or a Markdown table
+pub fn format(result: &QueryResult, rows: &[usize], format: Format) -> String {
+    let lines: Vec<String> = rows.iter().map(|&row| format_row(result, row, format)).collect();
+    match format {
+        Format::Markdown => {
+            let header = _markdown_line(result.columns.iter().map(|c| Some(c.name.as_str())));
+            let separator = format!("|{}", "---|".repeat(result.columns.len()));
+            vec![header, separator].into_iter().chain(lines).map(|l| format!("{}\n", l)).collect()
+        },
+        Format::Csv => {
+            let header = _csv_line(result.columns.iter().map(|c| Some(c.name.as_str())));
+            format!("{}\n", std::iter::once(header).chain(lines).collect::<Vec<String>>().join("\n"))
+        },
+        Format::Json if lines.is_empty() => String::from("[]\n"),
+        Format::Json => format!("[\n  {}\n]\n", lines.join(",\n  ")),
+        Format::Insert => lines.iter().map(|l| format!("{}\n", l)).collect(),
+    }
+}
+
+/// A single row : a CSV line without header, a JSON object, an INSERT statement or a line of a Markdown table
+pub fn format_row(result: &QueryResult, row: usize, format: Format) -> String {
+    let values = &result.rows[row];
+    match format {
+        Format::Markdown => _markdown_line(values.iter().map(Option::as_deref)),
+        Format::Csv => _csv_line(values.iter().map(Option::as_deref)),
+        Format::Json => {
+            let fields: Vec<String> = result.columns.iter().zip(values).map(|(column, value)| {
+                format!("{}: {}", _json_string(&column.name), _json_value(&column.type_, value.as_deref()))
+            }).collect();
+            format!("{{{}}}", fields.join(", "))
+        },
+        Format::Insert => {
+            let columns: Vec<String> = result.columns.iter().map(|c| _identifier(&c.name)).collect();
+            let values: Vec<String> = result.columns.iter().zip(values).map(|(column, value)| {
+                _sql_literal(&column.type_, value.as_deref())
+            }).collect();
+            format!("INSERT INTO {} ({}) VALUES ({});", INSERT_TABLE, columns.join(", "), values.join(", "))
+        },
+    }
+}
+
+/// NULL is an empty field, fields with separators, quotes or line breaks are quoted
+fn _csv_line<'a>(values: impl Iterator<Item = Option<&'a str>>) -> String {
+    values.map(|value| match value {
+        Some(v) if v.contains([',', '"', '\n', '\r']) => format!("\"{}\"", v.replace('"', "\"\"")),
+        Some(v) => v.to_string(),
+        None => String::new(),
+    }).collect::<Vec<String>>().join(",")
+}
+
+/// NULL is an empty cell, pipes are escaped and line breaks become `<br>`
+fn _markdown_line<'a>(values: impl Iterator<Item = Option<&'a str>>) -> String {
+    let cells: Vec<String> = values.map(|value| {
+        value.unwrap_or_default().replace('|', "\\|").replace("\r\n", "<br>").replace('\n', "<br>")
+    }).collect();
+    format!("| {} |", cells.join(" | "))
+}
+
+/// Writes `rows` of `result` to `path`, a leading `~/` is the home directory.
+/// Returns false without writing anything if the file exists and `overwrite` is not set
+pub fn to_file(path: &str, result: &QueryResult, rows: &[usize], format: Format, overwrite: bool) -> Result<bool, String> {
+    let file = match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    };
+    let mut options = OpenOptions::new();
+    if overwrite {
+        options.write(true).create(true).truncate(true);
+    } else {
+        options.write(true).create_new(true);
+    }
+    let error = |e: std::io::Error| format!("Error while writing {} : {}", path, e);
+    match options.open(&file) {
+        Ok(mut f) => f.write_all(self::format(result, rows, format).as_bytes()).map(|_| true).map_err(error),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(error(e)),
+    }
+}
+
+fn _json_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap()
+}
+
+/// Numbers, booleans and JSON values keep their type, anything else is a string
+fn _json_value(type_: &Type, value: Option<&str>) -> String {
+    match value {
+        None => String::from("null"),
+        // NaN and Infinity are not JSON numbers
+        Some(v) if sql::is_numeric(type_) && v.parse::<f64>().is_ok_and(f64::is_finite) => v.to_string(),
+        Some(v) if *type_ == Type::BOOL => String::from(if v == "t" { "true" } else { "false" }),
+        Some(v) if *type_ == Type::JSON || *type_ == Type::JSONB => v.to_string(),
+        Some(v) => _json_string(v),
+    }
+}
+
+/// Numbers as is, anything else quoted, the server casts it to the type of the column
+fn _sql_literal(type_: &Type, value: Option<&str>) -> String {
+    match value {
+        None => String::from("NULL"),
+        Some(v) if sql::is_numeric(type_) && v.parse::<f64>().is_ok_and(f64::is_finite) => v.to_string(),
+        Some(v) => format!("'{}'", v.replace('\'', "''")),
+    }
+}
+
+/// Always quoted, so that names like `order` or `user` need no list of reserved words
+fn _identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::ResultColumn;
+
+    fn _result() -> QueryResult {
+        let column = |name: &str, type_: Type| ResultColumn { name: name.to_string(), max_size: 0, type_ };
+        QueryResult {
+            columns: vec![column("id", Type::INT4), column("Name", Type::TEXT), column("ok", Type::BOOL)],
+            rows: vec![
+                vec![Some("1".to_string()), Some("it's, \"x\"".to_string()), Some("t".to_string())],
+                vec![Some("2".to_string()), None, Some("f".to_string())],
+            ],
+        }
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(format(&_result(), &[1, 0], Format::Csv), "id,Name,ok\n2,,f\n1,\"it's, \"\"x\"\"\",t\n");
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(format_row(&_result(), 1, Format::Json), r#"{"id": 2, "Name": null, "ok": false}"#);
+        assert_eq!(format(&_result(), &[], Format::Json), "[]\n");
+    }
+
+    #[test]
+    fn markdown() {
+        assert_eq!(format(&_result(), &[1], Format::Markdown), "| id | Name | ok |\n|---|---|---|\n| 2 |  | f |\n");
+        assert_eq!(Format::from_path("out.MD"), Some(Format::Markdown));
+        assert_eq!(Format::from_path("out"), None);
+    }
+
+    #[test]
+    fn insert() {
+        assert_eq!(format_row(&_result(), 0, Format::Insert), r#"INSERT INTO result ("id", "Name", "ok") VALUES (1, 'it''s, "x"', 't');"#);
+    }
+
+    #[test]
+    fn no_overwrite() {
+        let path = std::env::temp_dir().join(format!("rpsql.export.{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        assert_eq!(to_file(path, &_result(), &[0], Format::Csv, false), Ok(true));
+        assert_eq!(to_file(path, &_result(), &[1], Format::Csv, false), Ok(false));
+        assert_eq!(std::fs::read_to_string(path).unwrap().lines().count(), 2);
+        assert_eq!(to_file(path, &_result(), &[0, 1], Format::Csv, true), Ok(true));
+        assert_eq!(std::fs::read_to_string(path).unwrap().lines().count(), 3);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -45,7 +45,7 @@ impl History {
         if self.commands.is_empty() || &self.commands.get(0).unwrap()[..] != buffer {
             self.commands.insert(0, buffer.to_vec());
             if let Some(f) = &mut self.file {
-                let mut s = buffer.iter().fold(String::new(), |mut acc, &arg| { acc.push(arg); acc });
+                let mut s = _encode(buffer);
                 s.push('\n');
                 f.write_all(s.as_bytes()).unwrap();
             }
@@ -62,6 +62,60 @@ impl History {
         self.current_command = -1;
     }
 
+    /// Most recent command that is not a meta-command
+    pub fn last_query(&self) -> Option<Vec<char>> {
+        self.commands.iter().find(|c| c.first() != Some(&'\\')).cloned()
+    }
+
+    /// Rest of the most recent command starting with `prefix`
+    pub fn suggestion(&self, prefix: &[char]) -> Option<Vec<char>> {
+        self.commands.iter()
+            .find(|c| c.len() > prefix.len() && c.starts_with(prefix))
+            .map(|c| c[prefix.len()..].to_vec())
+    }
+
+    /// Every command, oldest first
+    pub fn lines(&self) -> Vec<String> {
+        self.commands.iter().rev().map(|c| c.iter().collect()).collect()
+    }
+
+    pub fn save_to(&self, path: &str) -> io::Result<()> {
+        let mut file = fs::File::create(path)?;
+        for l in self.lines() {
+            file.write_all(format!("{}\n", l).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Removes every command matching `pattern` (`*` and `?` wildcards, like psql patterns)
+    /// and returns how many were removed
+    pub fn delete_matching(&mut self, pattern: &str) -> io::Result<usize> {
+        let pattern = pattern.chars().collect::<Vec<char>>();
+        let len = self.commands.len();
+        self.commands.retain(|c| !_matches(&pattern, c));
+        self.reset_index();
+        self._rewrite_file()?;
+        Ok(len - self.commands.len())
+    }
+
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.commands.clear();
+        self.reset_index();
+        self._rewrite_file()
+    }
+
+    fn _rewrite_file(&mut self) -> io::Result<()> {
+        let lines = self.lines();
+        if let Some(f) = &mut self.file {
+            // The file is opened in append mode, so writes always go to the (new) end
+            f.set_len(0)?;
+            for l in lines {
+                f.write_all(format!("{}\n", _encode(&l.chars().collect::<Vec<char>>())).as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn load_from_file() -> History {
         let mut history = History::new();
         let mut path = dirs::config_dir().unwrap_or_else(|| {
@@ -92,7 +146,7 @@ impl History {
                     file.read_to_string(&mut contents).unwrap();
                     for l in contents.lines() {
                         if !l.trim().is_empty() {
-                            history.push(&l.chars().collect::<Vec<char>>()[..]);
+                            history.push(&_decode(l)[..]);
                         }
                     }
                     history.file = Some(file);
@@ -105,3 +159,114 @@ impl History {
         history
     }
 }
+
+/// Newlines of a command are stored as NUL, which cannot be part of a query,
+/// so that every other line of the file is read as it was written
+const NEWLINE_MARK: char = '\0';
+
+/// Commands are stored one per line
+fn _encode(command: &[char]) -> String {
+    command.iter().map(|&c| if c == '\n' { NEWLINE_MARK } else { c }).collect()
+}
+
+fn _decode(line: &str) -> Vec<char> {
+    line.chars().map(|c| if c == NEWLINE_MARK { '\n' } else { c }).collect()
+}
+
+/// Whole-command wildcard match, iterative : after a mismatch only the last `*` is retried,
+/// one character further, which keeps it O(pattern * command)
+fn _matches(pattern: &[char], command: &[char]) -> bool {
+    let (mut p, mut c) = (0, 0);
+    // Position of the last `*` and of the command where it was tried
+    let mut star: Option<(usize, usize)> = None;
+    while c < command.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, c));
+                p += 1;
+            },
+            Some(&x) if x == '?' || x == command[c] => {
+                p += 1;
+                c += 1;
+            },
+            _ => match star {
+                Some((star_p, star_c)) => {
+                    p = star_p + 1;
+                    c = star_c + 1;
+                    star = Some((star_p, c));
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&x| x == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _history(commands: &[&str]) -> History {
+        let mut history = History::new();
+        for c in commands {
+            history.push(&c.chars().collect::<Vec<char>>()[..]);
+        }
+        history
+    }
+
+    #[test]
+    fn encode_decode() {
+        let command = "select E'\\\\n',\n  2".chars().collect::<Vec<char>>();
+
+        assert_eq!(_encode(&command), "select E'\\\\n',\0  2");
+        assert_eq!(_decode(&_encode(&command)), command);
+        // Lines written before multi-line commands were kept together
+        assert_eq!(_decode("select '\\n'"), "select '\\n'".chars().collect::<Vec<char>>());
+    }
+
+    #[test]
+    fn lines_oldest_first() {
+        let history = _history(&["select 1", "select 2"]);
+
+        assert_eq!(history.lines(), vec!["select 1", "select 2"]);
+    }
+
+    #[test]
+    fn suggestion_is_most_recent() {
+        let history = _history(&["select 1 from a", "select 2", "select"]);
+        let prefix = "select ".chars().collect::<Vec<char>>();
+
+        assert_eq!(history.suggestion(&prefix), Some(vec!['2']));
+        assert_eq!(history.suggestion(&"select 2".chars().collect::<Vec<char>>()), None);
+    }
+
+    #[test]
+    fn delete_matching() {
+        let mut history = _history(&["select 1", "drop table a", "select 2", "drop table b"]);
+
+        assert_eq!(history.delete_matching("drop *").unwrap(), 2);
+        assert_eq!(history.lines(), vec!["select 1", "select 2"]);
+        assert_eq!(history.delete_matching("select ?").unwrap(), 2);
+        assert!(history.lines().is_empty());
+    }
+
+    #[test]
+    fn delete_matching_is_anchored() {
+        let mut history = _history(&["select 1", "select 12"]);
+
+        assert_eq!(history.delete_matching("select 1").unwrap(), 1);
+        assert_eq!(history.lines(), vec!["select 12"]);
+    }
+
+    #[test]
+    fn wildcards() {
+        let matches = |pattern: &str, command: &str| _matches(&pattern.chars().collect::<Vec<char>>(), &command.chars().collect::<Vec<char>>());
+        assert!(matches("*", ""));
+        assert!(matches("s*t ?", "select 1"));
+        assert!(matches("*a*b", "xaab"));
+        assert!(!matches("*a*b", "xaabc"));
+        assert!(!matches("a?", "a"));
+        // Would take exponential time with backtracking
+        assert!(!matches("*a*a*a*a*a*a*a*b", &"a".repeat(10_000)));
+    }
+}
@@ -0,0 +1,240 @@
+/// What a character of a SQL text is part of
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Context {
+    Code,
+    SingleQuote,
+    DoubleQuote,
+    DollarQuote,
+    LineComment,
+    BlockComment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct State {
+    pub context: Context,
+    /// Number of open parentheses
+    pub depth: usize,
+}
+
+/// State before every character of `text`, and after the last one
+pub fn scan(text: &[char]) -> Vec<State> {
+    let mut states = Vec::with_capacity(text.len() + 1);
+    let mut state = State { context: Context::Code, depth: 0 };
+    let mut comment_depth = 0;
+    let mut tag: &[char] = &[];
+    let mut escapes = false;
+    let mut i = 0;
+
+    while i < text.len() {
+        let before = state;
+        let next = text.get(i + 1).copied();
+        let mut len = 1;
+        match state.context {
+            Context::Code => match text[i] {
+                '\'' => {
+                    // E'...' strings accept backslash escapes
+                    escapes = i > 0 && (text[i - 1] == 'e' || text[i - 1] == 'E') && (i < 2 || !_is_identifier(text[i - 2]));
+                    state.context = Context::SingleQuote;
+                },
+                '"' => state.context = Context::DoubleQuote,
+                '-' if next == Some('-') => {
+                    state.context = Context::LineComment;
+                    len = 2;
+                },
+                '/' if next == Some('*') => {
+                    state.context = Context::BlockComment;
+                    comment_depth = 1;
+                    len = 2;
+                },
+                '(' => state.depth += 1,
+                ')' => state.depth = state.depth.saturating_sub(1),
+                '$' => if let Some(t) = _dollar_tag(text, i) {
+                    tag = t;
+                    state.context = Context::DollarQuote;
+                    len = tag.len() + 2;
+                },
+                _ => {},
+            },
+            Context::SingleQuote => match text[i] {
+                '\\' if escapes => len = 2,
+                '\'' => state.context = Context::Code,
+                _ => {},
+            },
+            Context::DoubleQuote => if text[i] == '"' {
+                state.context = Context::Code;
+            },
+            Context::LineComment => if text[i] == '\n' {
+                state.context = Context::Code;
+            },
+            Context::BlockComment => match (text[i], next) {
+                // Block comments nest
+                ('/', Some('*')) => {
+                    comment_depth += 1;
+                    len = 2;
+                },
+                ('*', Some('/')) => {
+                    comment_depth -= 1;
+                    if comment_depth == 0 {
+                        state.context = Context::Code;
+                    }
+                    len = 2;
+                },
+                _ => {},
+            },
+            Context::DollarQuote => if text[i] == '$' && text[i + 1..].starts_with(tag) && text.get(i + tag.len() + 1) == Some(&'$') {
+                state.context = Context::Code;
+                len = tag.len() + 2;
+            },
+        }
+        len = std::cmp::min(len, text.len() - i);
+        states.extend(std::iter::repeat_n(before, len));
+        i += len;
+    }
+    states.push(state);
+    states
+}
+
+/// Brackets and quotes of a SQL text, without those inside literals and comments
+#[derive(Debug, Default)]
+pub struct Pairs {
+    /// Positions of the opening and closing characters
+    pub pairs: Vec<(usize, usize)>,
+    pub unmatched: Vec<usize>,
+}
+
+impl Pairs {
+    /// Position of the character matching the one at `index`
+    pub fn matching(&self, index: usize) -> Option<usize> {
+        self.pairs.iter().find_map(|&(open, close)| {
+            if open == index {
+                Some(close)
+            } else if close == index {
+                Some(open)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+pub fn pairs(text: &[char]) -> Pairs {
+    let states = scan(text);
+    let mut res = Pairs::default();
+    let mut open_brackets: Vec<usize> = Vec::new();
+    let mut open_quote = None;
+
+    for (i, &c) in text.iter().enumerate() {
+        let (before, after) = (states[i].context, states[i + 1].context);
+        match c {
+            '(' | '[' | '{' if before == Context::Code => open_brackets.push(i),
+            ')' | ']' | '}' if before == Context::Code => {
+                let opening = match c {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+                match open_brackets.last() {
+                    Some(&open) if text[open] == opening => {
+                        open_brackets.pop();
+                        res.pairs.push((open, i));
+                    },
+                    _ => res.unmatched.push(i),
+                }
+            },
+            '\'' | '"' if before == Context::Code && after != Context::Code => {
+                // A doubled quote is part of the literal, the pair closed just before goes on
+                match res.pairs.last() {
+                    Some(&(open, close)) if close + 1 == i && text[close] == c => {
+                        res.pairs.pop();
+                        open_quote = Some(open);
+                    },
+                    _ => open_quote = Some(i),
+                }
+            },
+            '\'' | '"' if before != Context::Code && after == Context::Code => {
+                if let Some(open) = open_quote.take() {
+                    res.pairs.push((open, i));
+                }
+            },
+            _ => {},
+        }
+    }
+    res.unmatched.extend(open_brackets);
+    res.unmatched.extend(open_quote);
+    res.unmatched.sort_unstable();
+    res.pairs.sort_unstable();
+    res
+}
+
+/// Tag of the `$tag$` starting at `start`, `$$` has an empty tag
+fn _dollar_tag(text: &[char], start: usize) -> Option<&[char]> {
+    if start > 0 && _is_identifier(text[start - 1]) {
+        return None;
+    }
+    let end = start + 1 + text[start + 1..].iter().position(|&c| !_is_identifier(c)).unwrap_or(text.len() - start - 1);
+    let tag = &text[start + 1..end];
+    if text.get(end) == Some(&'$') && !tag.first().is_some_and(|c| c.is_ascii_digit()) {
+        Some(tag)
+    } else {
+        None
+    }
+}
+
+fn _is_identifier(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _end_state(s: &str) -> State {
+        *scan(&s.chars().collect::<Vec<char>>()).last().unwrap()
+    }
+
+    #[test]
+    fn quotes_and_comments() {
+        assert_eq!(_end_state("select 'it''s").context, Context::SingleQuote);
+        assert_eq!(_end_state("select 'it''s'").context, Context::Code);
+        assert_eq!(_end_state("select E'\\'").context, Context::SingleQuote);
+        assert_eq!(_end_state("select \"a").context, Context::DoubleQuote);
+        assert_eq!(_end_state("select 1 -- (").context, Context::LineComment);
+        assert_eq!(_end_state("select 1 -- (").depth, 0);
+        assert_eq!(_end_state("/* a /* b */").context, Context::BlockComment);
+        assert_eq!(_end_state("/* a /* b */ */").context, Context::Code);
+    }
+
+    #[test]
+    fn dollar_quotes() {
+        assert_eq!(_end_state("select $$ ' $$").context, Context::Code);
+        assert_eq!(_end_state("select $fn$ $$ ").context, Context::DollarQuote);
+        assert_eq!(_end_state("select $fn$ $$ $fn$").context, Context::Code);
+        assert_eq!(_end_state("select $fn$a$fn$").context, Context::Code);
+        assert_eq!(_end_state("select $1, a$b").context, Context::Code);
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(_end_state("select (1 + (2").depth, 2);
+        assert_eq!(_end_state("select (1 + ')'").depth, 1);
+        assert_eq!(_end_state("select (1)").depth, 0);
+    }
+
+    #[test]
+    fn matching_pairs() {
+        let pairs = pairs(&"select f('a)', \"b\"\"c\") + (]".chars().collect::<Vec<char>>());
+
+        assert_eq!(pairs.matching(8), Some(21));
+        assert_eq!(pairs.matching(12), Some(9));
+        assert_eq!(pairs.matching(15), Some(20));
+        assert_eq!(pairs.matching(17), None);
+        assert_eq!(pairs.unmatched, vec![25, 26]);
+    }
+
+    #[test]
+    fn state_before_each_character() {
+        let states = scan(&"a'b'".chars().collect::<Vec<char>>());
+        let contexts: Vec<Context> = states.iter().map(|s| s.context).collect();
+        assert_eq!(contexts, vec![Context::Code, Context::Code, Context::SingleQuote, Context::SingleQuote, Context::Code]);
+    }
+}
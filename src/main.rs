@@ -5,59 +5,210 @@ mod sql;
 mod ui;
 mod history;
 mod pgpass;
+mod command;
+mod settings;
+mod editor;
+mod lexer;
+mod prompt;
+mod regex;
+mod pager;
+mod export;
+mod results;
 
 use parse_args::Config;
 use connection_options::ConnectionOptions;
-use std::io::Error;
-use termion::raw::IntoRawMode;
+use std::io::{Error, Stdout};
+use std::path::Path;
+use std::time::Instant;
+use termion::raw::{IntoRawMode, RawTerminal};
 use history::History;
+use command::Command;
+use settings::Settings;
+use results::ResultHistory;
+use export::Format;
+use prompt::{Prompt, PromptInfo};
 use ui::event_loop::{self, Event};
+use ui::keymap::Keymap;
 use dirs;
 
-fn _handle_query_result(client: &mut sql::Client, query : &str) {
-    let res = sql::handle_query(client, query);
-    if let Err(e) = res {
-        event_loop::display_error_string(&e);
-    } else {
-        // event_loop::display_vec(&res.unwrap()[..]);
-        event_loop::display_on_alternate_screen(&res.unwrap()[..]);
+/// The result is kept for `\results` once displayed
+fn _handle_query_result(client: &mut sql::Client, query : &str, stdout: &RawTerminal<Stdout>, results: &mut ResultHistory, settings: &Settings, keymap: &Keymap) -> sql::QueryOutcome {
+    let start = Instant::now();
+    match sql::handle_query(client, query) {
+        Ok(result) => {
+            let duration = start.elapsed();
+            event_loop::display_result(&result, stdout, settings, keymap);
+            results.push(query, duration, result);
+            sql::QueryOutcome::Succeeded
+        },
+        Err(e) => {
+            event_loop::display_error_string(&e.message);
+            e.outcome
+        },
     }
 }
 
+/// Returns the edited text for edition commands
+fn _handle_command(command: Command, client: &mut sql::Client, stdout: &RawTerminal<Stdout>, history: &mut History, results: &ResultHistory,
+                   settings: &mut Settings, keymap: &Keymap) -> Option<String> {
+    let res = match command {
+        Command::Bindings => {
+            event_loop::display_vec(&keymap.lines()[..]);
+            Ok(None)
+        },
+        Command::History(None) => {
+            event_loop::display_vec(&history.lines()[..]);
+            Ok(None)
+        },
+        Command::History(Some(file)) => history.save_to(&file).map(|_| {
+            event_loop::display_string(&format!("Wrote history to file \"{}\".", file));
+            None
+        }).map_err(|e| format!("Error while saving history : {}", e)),
+        Command::HistoryDelete(pattern) => history.delete_matching(&pattern).map(|n| {
+            event_loop::display_string(&format!("Deleted {} history entries.", n));
+            None
+        }).map_err(|e| format!("Error while updating history : {}", e)),
+        Command::HistoryClear => history.clear().map(|_| {
+            event_loop::display_string("History cleared.");
+            None
+        }).map_err(|e| format!("Error while updating history : {}", e)),
+        Command::Edit(Some(file), line) => editor::edit_file(stdout, Path::new(&file), line)
+            .map(Some)
+            .map_err(|e| format!("Error while editing {} : {}", file, e)),
+        Command::Edit(None, line) => {
+            let last_query = history.last_query().unwrap_or_default().iter().collect::<String>();
+            _edit_text(stdout, &last_query, line)
+        },
+        Command::EditFunction(name, line) => sql::function_definition(client, name.as_deref())
+            .and_then(|definition| _edit_text(stdout, &definition, line)),
+        Command::EditView(name, line) => sql::view_definition(client, name.as_deref())
+            .and_then(|definition| _edit_text(stdout, &definition, line)),
+        Command::Results => {
+            event_loop::display_vec(&results.lines()[..]);
+            Ok(None)
+        },
+        Command::ResultsShow(id) => results.get(id).map(|kept| {
+            event_loop::display_result(&kept.result, stdout, settings, keymap);
+            None
+        }),
+        Command::ResultsExport(id, file, format) => results.get(id).and_then(|kept| {
+            let format = match format {
+                Some(name) => Format::from_name(&name).ok_or_else(|| format!("Unknown format {}, use csv, json, insert or markdown", name))?,
+                None => Format::from_path(&file).unwrap_or(Format::Csv),
+            };
+            let rows: Vec<usize> = (0..kept.result.rows.len()).collect();
+            let written = export::to_file(&file, &kept.result, &rows, format, false)?
+                || (event_loop::confirm(&format!("{} exists, overwrite it ? [y/N] ", file))
+                    && export::to_file(&file, &kept.result, &rows, format, true)?);
+            if !written {
+                return Ok(None);
+            }
+            event_loop::display_string(&format!("Wrote {} rows as {} to {}.", rows.len(), format.name(), file));
+            Ok(None)
+        }),
+        Command::ResultsCompare(a, b) => results.get(a).and_then(|a| results.get(b).map(|b| {
+            event_loop::display_vec(&results::compare(a, b)[..]);
+            None
+        })),
+        Command::ResultsDiff(a, b, keys) => results.get(a)
+            .and_then(|a| results.get(b).and_then(|b| results::diff(a, b, &keys)))
+            .map(|diff| {
+                event_loop::display_diff(&diff, settings, keymap);
+                event_loop::display_string(&diff.summary);
+                None
+            }),
+        Command::Pset(option, value) => settings.pset(&option, value.as_deref()).map(|message| {
+            event_loop::display_string(&message);
+            None
+        }),
+    };
+    res.unwrap_or_else(|e| {
+        event_loop::display_error_string(&e);
+        None
+    })
+}
+
+fn _edit_text(stdout: &RawTerminal<Stdout>, text: &str, line: Option<usize>) -> Result<Option<String>, String> {
+    editor::edit_text(stdout, text, line)
+        .map(Some)
+        .map_err(|e| format!("Error while editing : {}", e))
+}
+
+/// Without a terminal, the result is printed as is
 fn _handle_no_tty(client: &mut sql::Client) -> Result<(), String>{
     match event_loop::get_direct_input() {
         Ok(query) => {
-            _handle_query_result(client, query.as_str());
+            match sql::handle_query(client, query.as_str()) {
+                Ok(result) => event_loop::display_vec(&result.lines()[..]),
+                Err(e) => event_loop::display_error_string(&e.message),
+            }
             Ok(())
         },
         Err(e) => Err(e.to_string())
     }
 }
 
-fn _main_loop(connection_options: &ConnectionOptions, password: Option<String>) -> Result<(), String> {
+fn _main_loop(connection_options: &ConnectionOptions, profile: Option<&str>, password: Option<String>, settings: &mut Settings) -> Result<(), String> {
     let mut client = sql::try_connect(&connection_options, password)?;
+    // Reported once the screen is ready, it is cleared by init
+    let (keymap, binding_errors) = Keymap::with_bindings(&settings.bindings);
+    let report_binding_errors = || {
+        for e in &binding_errors {
+            event_loop::display_error_string(&format!("Ignoring key binding : {}", e));
+        }
+    };
 
     if !termion::is_tty(&std::io::stdout()) || !termion::is_tty(&std::io::stdin()) {
+        report_binding_errors();
         return _handle_no_tty(&mut client)
     }
     else {
         event_loop::init();
+        report_binding_errors();
         let mut again = true;
         let mut stdout = std::io::stdout().into_raw_mode().unwrap();
         let mut history = History::load_from_file();
+        let mut input = Vec::new();
+        let superuser = sql::is_superuser(&mut client);
+        let mut results = ResultHistory::new(settings.kept_results, settings.kept_results_mb * 1024 * 1024);
+        // Updated after every query, for %x and before quitting
+        let mut transaction = sql::TransactionStatus::Idle;
 
         while again {
-            match event_loop::get_input(&mut stdout, &mut history) {
+            let prompt = Prompt::new(settings, PromptInfo::new(connection_options, profile, superuser, transaction));
+            match event_loop::get_input(&mut stdout, &mut history, settings, &keymap, prompt, input.split_off(0)) {
                 Event::Buffer(query) => {
                     print!("\r\n");
-                    if !query.trim().is_empty() {
-                        _handle_query_result(&mut client, query.as_str());
+                    match Command::parse(&query) {
+                        Ok(Some(command)) => {
+                            if let Some(text) = _handle_command(command, &mut client, &stdout, &mut history, &results, settings, &keymap) {
+                                let text_chars = text.chars().collect::<Vec<char>>();
+                                if editor::is_terminated(&text) {
+                                    history.push_and_save(&text_chars);
+                                    let outcome = _handle_query_result(&mut client, &text, &stdout, &mut results, settings, &keymap);
+                                    transaction = transaction.after(&text, outcome);
+                                } else {
+                                    input = text_chars;
+                                }
+                            }
+                        },
+                        Ok(None) => {
+                            if !query.trim().is_empty() {
+                                let outcome = _handle_query_result(&mut client, query.as_str(), &stdout, &mut results, settings, &keymap);
+                                transaction = transaction.after(&query, outcome);
+                            }
+                        },
+                        Err(e) => event_loop::display_error_string(&e),
                     }
                 },
-                Event::Quit => again = false,
+                Event::Quit => {
+                    let open = matches!(transaction, sql::TransactionStatus::InTransaction | sql::TransactionStatus::Failed);
+                    again = open && !event_loop::confirm("A transaction is in progress and will be rolled back, quit anyway ? [y/N] ");
+                },
                 Event::None => {}
             }
         }
+        event_loop::quit();
     }
 
     Ok(())
@@ -66,13 +217,13 @@ fn _main_loop(connection_options: &ConnectionOptions, password: Option<String>)
 
 fn main() {
     let config : Config = parse_args::parse();
-    let res: Result<ConnectionOptions, Error> = match config {
-        Config::None => profile::choose(),
-        Config::Profile(p) => profile::load(&p),
-        Config::ConnectionOptions(c) => Ok(c)
+    let res: Result<(Option<String>, ConnectionOptions), Error> = match config {
+        Config::None => profile::choose().map(|(name, c)| (Some(name), c)),
+        Config::Profile(p) => profile::load(&p).map(|c| (Some(p), c)),
+        Config::ConnectionOptions(c) => Ok((None, c))
     };
     match res {
-        Ok(options) => {
+        Ok((profile_name, options)) => {
             let password = if let Some(ref mut home) = dirs::home_dir() {
                 home.push(".pgpass");
                 pgpass::parse(&home, &options)
@@ -80,7 +231,8 @@ fn main() {
                 None
             };
 
-            if let Err(e) = _main_loop(&options, password) {
+            let mut settings = settings::load();
+            if let Err(e) = _main_loop(&options, profile_name.as_deref(), password, &mut settings) {
                 eprintln!("{}", e);
                 std::process::exit(1);
             }
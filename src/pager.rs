@@ -0,0 +1,35 @@
+use std::env;
+use std::io::{self, Stdout, Write};
+use std::process::{self, Stdio};
+use termion::raw::RawTerminal;
+use crate::ui::event::{BRACKETED_PASTE_ON, BRACKETED_PASTE_OFF};
+
+const DEFAULT_PAGER: &str = "less -S";
+
+/// Shows `text` in `$PSQL_PAGER` or `$PAGER` (`less -S` if none is set)
+pub fn page(stdout: &RawTerminal<Stdout>, text: &str) -> io::Result<()> {
+    let pager = env::var("PSQL_PAGER")
+        .or_else(|_| env::var("PAGER"))
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+    let mut words = pager.split_whitespace();
+    let mut command = process::Command::new(words.next().unwrap());
+    command.args(words).stdin(Stdio::piped());
+
+    print!("{}", BRACKETED_PASTE_OFF);
+    io::stdout().flush()?;
+    stdout.suspend_raw_mode()?;
+    let status = command.spawn().and_then(|mut child| {
+        // The pager may quit before reading everything
+        let _ = child.stdin.take().unwrap().write_all(text.as_bytes());
+        child.wait()
+    });
+    stdout.activate_raw_mode()?;
+    print!("{}", BRACKETED_PASTE_ON);
+    let status = status.map_err(|e| io::Error::new(e.kind(), format!("could not run pager {} : {}", pager, e)))?;
+    if !status.success() {
+        return Err(io::Error::other(format!("pager {} exited with {}", pager, status)));
+    }
+    Ok(())
+}
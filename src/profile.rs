@@ -6,7 +6,8 @@ use crate::connection_options::ConnectionOptions;
 
 const PROFILES_DIR: &str = "rpsql/profiles";
 
-pub fn choose() -> Result<ConnectionOptions, io::Error> {
+/// Returns the name of the chosen profile with its options
+pub fn choose() -> Result<(String, ConnectionOptions), io::Error> {
     let (dir, profiles) = _get_dir_and_profiles()?;
 
     println!("(0) Create new profile");
@@ -46,7 +47,7 @@ fn _get_dir_and_profiles() -> Result<(path::PathBuf, Vec<path::PathBuf>), io::Er
     Ok((dir, profiles))
 }
 
-fn _get_user_choice(dir: &path::PathBuf, profiles: &[path::PathBuf]) -> Result<ConnectionOptions, io::Error> {
+fn _get_user_choice(dir: &path::PathBuf, profiles: &[path::PathBuf]) -> Result<(String, ConnectionOptions), io::Error> {
     loop {
         let mut buffer = String::new();
         print!("Choose your profile : ");
@@ -58,7 +59,7 @@ fn _get_user_choice(dir: &path::PathBuf, profiles: &[path::PathBuf]) -> Result<C
                 println!("Creating new profile");
                 let (profile_name, connect_options) = _create_new_profile();
                 _save_profile(&dir, &profile_name, &connect_options)?;
-                return Ok(connect_options);
+                return Ok((profile_name, connect_options));
             }
             _ => {
                 println!("Using profile {}", choice);
@@ -78,7 +79,8 @@ fn _get_user_choice(dir: &path::PathBuf, profiles: &[path::PathBuf]) -> Result<C
                         continue;
                     }
                 }
-                return _load_profile(&profile_name);
+                let name = profile_name.file_stem().unwrap_or_default().to_string_lossy().to_string();
+                return _load_profile(&profile_name).map(|c| (name, c));
             }
         }
     }
@@ -0,0 +1,175 @@
+use unicode_width::UnicodeWidthStr;
+use crate::connection_options::ConnectionOptions;
+use crate::lexer::{Context, State};
+use crate::settings::Settings;
+use crate::sql::TransactionStatus;
+
+/// What the prompt escapes are replaced with
+#[derive(Clone)]
+pub struct PromptInfo {
+    pub user: String,
+    pub host: String,
+    pub port: String,
+    pub dbname: String,
+    pub profile: Option<String>,
+    pub superuser: bool,
+    pub transaction: TransactionStatus,
+}
+
+impl PromptInfo {
+    pub fn new(options: &ConnectionOptions, profile: Option<&str>, superuser: bool, transaction: TransactionStatus) -> PromptInfo {
+        PromptInfo {
+            user: options.user.clone(),
+            host: options.host.clone(),
+            port: options.port.clone(),
+            dbname: options.dbname.clone(),
+            profile: profile.map(String::from),
+            superuser,
+            transaction,
+        }
+    }
+}
+
+/// The `prompt1` and `prompt2` settings, with psql's `%` escapes
+#[derive(Clone)]
+pub struct Prompt {
+    prompt1: String,
+    prompt2: String,
+    info: PromptInfo,
+}
+
+impl Prompt {
+    pub fn new(settings: &Settings, info: PromptInfo) -> Prompt {
+        Prompt {
+            prompt1: settings.prompt1.clone(),
+            prompt2: settings.prompt2.clone(),
+            info,
+        }
+    }
+
+    /// Text and display width of the prompt of the first line
+    pub fn first(&self) -> (String, usize) {
+        expand(&self.prompt1, &self.info, '=')
+    }
+
+    /// Text and display width of the prompt of a line starting in `state`
+    pub fn continuation(&self, state: &State) -> (String, usize) {
+        let c = match state.context {
+            Context::SingleQuote => '\'',
+            Context::DoubleQuote => '"',
+            Context::DollarQuote => '$',
+            Context::BlockComment => '*',
+            _ if state.depth > 0 => '(',
+            _ => '-',
+        };
+        expand(&self.prompt2, &self.info, c)
+    }
+}
+
+/// Replaces the escapes of `format`, `continuation` is the value of `%R`.
+/// Returns the text and its display width, which does not count what is between `%[` and `%]`
+pub fn expand(format: &str, info: &PromptInfo, continuation: char) -> (String, usize) {
+    let mut res = String::new();
+    let mut width = 0;
+    let mut visible = true;
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let text = if c != '%' {
+            c.to_string()
+        } else {
+            match chars.next() {
+                None => String::from("%"),
+                Some('n') => info.user.clone(),
+                Some('m') => _short_host(&info.host),
+                Some('M') => if _is_local(&info.host) { String::from("[local]") } else { info.host.clone() },
+                Some('>') => info.port.clone(),
+                Some('/') => info.dbname.clone(),
+                Some('P') => info.profile.clone().unwrap_or_default(),
+                Some('x') => String::from(match info.transaction {
+                    TransactionStatus::Idle => "",
+                    TransactionStatus::InTransaction => "*",
+                    TransactionStatus::Failed => "!",
+                }),
+                Some('#') => String::from(if info.superuser { "#" } else { ">" }),
+                Some('R') => continuation.to_string(),
+                Some('[') => {
+                    visible = false;
+                    continue;
+                },
+                Some(']') => {
+                    visible = true;
+                    continue;
+                },
+                // %033 is the character with octal code 033, the escape of color sequences
+                Some(d) if d.is_digit(8) => {
+                    let mut code = d.to_digit(8).unwrap();
+                    for _ in 0..2 {
+                        match chars.peek().and_then(|c| c.to_digit(8)) {
+                            Some(n) => {
+                                code = code * 8 + n;
+                                chars.next();
+                            },
+                            None => break,
+                        }
+                    }
+                    std::char::from_u32(code).map(String::from).unwrap_or_default()
+                },
+                Some(other) => other.to_string(),
+            }
+        };
+        if visible {
+            width += text.width();
+        }
+        res.push_str(&text);
+    }
+    (res, width)
+}
+
+fn _is_local(host: &str) -> bool {
+    host.is_empty() || host.starts_with('/')
+}
+
+/// Host name up to the first dot, addresses are kept whole
+fn _short_host(host: &str) -> String {
+    if _is_local(host) {
+        String::from("[local]")
+    } else if host.parse::<std::net::IpAddr>().is_ok() {
+        host.to_string()
+    } else {
+        host.split('.').next().unwrap_or_default().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _info() -> PromptInfo {
+        PromptInfo {
+            user: String::from("alice"),
+            host: String::from("db.example.com"),
+            port: String::from("5432"),
+            dbname: String::from("shop"),
+            profile: Some(String::from("prod")),
+            superuser: false,
+            transaction: TransactionStatus::InTransaction,
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        let (text, width) = expand("%n@%m:%>/%/ [%P] %M%R%x%# ", &_info(), '=');
+        assert_eq!(text, "alice@db:5432/shop [prod] db.example.com=*> ");
+        assert_eq!(width, text.len());
+        assert_eq!(expand("100%%", &_info(), '=').0, "100%");
+        assert_eq!(expand("%m", &PromptInfo { host: String::from("127.0.0.1"), .._info() }, '=').0, "127.0.0.1");
+    }
+
+    #[test]
+    fn invisible_sequences() {
+        let (text, width) = expand("%[%033[1;31m%]%/%[%033[0m%]> ", &_info(), '=');
+        assert_eq!(text, "\x1b[1;31mshop\x1b[0m> ");
+        assert_eq!(width, 6);
+    }
+}
@@ -0,0 +1,93 @@
+use ::regex::RegexBuilder;
+
+/// Regular expressions for searches, with the syntax of the `regex` crate : `.`, `[...]`, `*`, `+`, `?`, `|`,
+/// `(...)`, `^`, `$`, `\d`, `\w`, `\s`...
+/// Matching takes linear time and no recursion, whatever the pattern and however long the line.
+#[derive(Debug)]
+pub struct Regex {
+    regex: ::regex::Regex,
+}
+
+impl Regex {
+    /// Like in vim with smartcase, a pattern without uppercase letters ignores case
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        RegexBuilder::new(pattern)
+            .case_insensitive(_smartcase(pattern))
+            .build()
+            .map(|regex| Regex { regex })
+            .map_err(|e| _error_message(&e))
+    }
+
+    /// Matches `text` as is
+    pub fn literal(text: &str) -> Regex {
+        let regex = RegexBuilder::new(&::regex::escape(text)).case_insensitive(_smartcase(text)).build().unwrap();
+        Regex { regex }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+
+    /// `[start, end)` in bytes of every match that does not overlap the previous one, empty matches excluded
+    pub fn find_all(&self, text: &str) -> Vec<(usize, usize)> {
+        self.regex.find_iter(text).filter(|m| !m.is_empty()).map(|m| (m.start(), m.end())).collect()
+    }
+}
+
+fn _smartcase(pattern: &str) -> bool {
+    !pattern.chars().any(char::is_uppercase)
+}
+
+/// The last line of the error, the others show the pattern and where the error is
+fn _error_message(error: &::regex::Error) -> String {
+    let message = error.to_string();
+    let last = message.lines().last().unwrap_or_default();
+    last.strip_prefix("error: ").unwrap_or(last).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _find(pattern: &str, text: &str) -> Option<(usize, usize)> {
+        Regex::new(pattern).unwrap().find_all(text).first().copied()
+    }
+
+    #[test]
+    fn matching() {
+        assert_eq!(_find("b+c", "abbbcd"), Some((1, 5)));
+        assert_eq!(_find("a.c", "xxabcabc"), Some((2, 5)));
+        assert_eq!(_find("^ab", "cab"), None);
+        assert_eq!(_find("b$", "abab"), Some((3, 4)));
+        assert_eq!(_find("(ab|cd)+e", "xcdabe"), Some((1, 6)));
+        assert_eq!(_find("colou?r", "color"), Some((0, 5)));
+        assert_eq!(_find("[0-9]+\\.\\d*", "v 12.5 "), Some((2, 6)));
+        assert_eq!(_find("[^0-9 ]+", "12 CD"), Some((3, 5)));
+        assert_eq!(_find("\\w+@\\S+", "mail a_b@x.org"), Some((5, 14)));
+        assert_eq!(_find("(a*)*b", "aaab"), Some((0, 4)));
+    }
+
+    #[test]
+    fn case() {
+        assert_eq!(_find("abc", "xABC"), Some((1, 4)));
+        assert_eq!(_find("Abc", "xABC"), None);
+        assert_eq!(_find("[a-c]+", "ABC"), Some((0, 3)));
+        assert_eq!(Regex::literal("a.c").find_all("a.c abc A.C"), vec![(0, 3), (8, 11)]);
+    }
+
+    #[test]
+    fn long_lines() {
+        let line = "x".repeat(100_000);
+        assert!(!Regex::new("x*y").unwrap().is_match(&line));
+        assert!(!Regex::new("(x*)*y").unwrap().is_match(&line));
+        assert_eq!(Regex::new(".*x$").unwrap().find_all(&line), vec![(0, 100_000)]);
+    }
+
+    #[test]
+    fn errors() {
+        for pattern in &["(ab", "ab)", "*a", "[a-", "a\\", "[z-a]"] {
+            assert!(Regex::new(pattern).is_err(), "{}", pattern);
+        }
+        assert_eq!(Regex::new("(ab").unwrap_err(), "unclosed group");
+    }
+}
@@ -0,0 +1,249 @@
+use postgres_types::Type;
+use std::cmp::max;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+use crate::sql::{QueryResult, ResultColumn};
+
+/// Characters of the query shown in the list
+const QUERY_WIDTH: usize = 60;
+
+/// A result kept after its pager is closed
+#[derive(Debug)]
+pub struct KeptResult {
+    /// Stays the same when older results are dropped
+    pub id: usize,
+    pub query: String,
+    pub duration: Duration,
+    pub result: QueryResult,
+    size: usize,
+}
+
+/// The last results, in memory only, the oldest go first when there are too many or they are too big
+#[derive(Debug)]
+pub struct ResultHistory {
+    results: VecDeque<KeptResult>,
+    next_id: usize,
+    max_count: usize,
+    max_size: usize,
+    size: usize,
+}
+
+impl ResultHistory {
+    /// `max_size` in bytes of values
+    pub fn new(max_count: usize, max_size: usize) -> ResultHistory {
+        ResultHistory {
+            results: VecDeque::new(),
+            next_id: 1,
+            max_count,
+            max_size,
+            size: 0,
+        }
+    }
+
+    /// Returns the id of the result, `None` if it is bigger than the limit alone
+    pub fn push(&mut self, query: &str, duration: Duration, result: QueryResult) -> Option<usize> {
+        let size = _size(&result);
+        if self.max_count == 0 || size > self.max_size {
+            return None;
+        }
+        while self.results.len() >= self.max_count || self.size + size > self.max_size {
+            let dropped = self.results.pop_front().unwrap();
+            self.size -= dropped.size;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.size += size;
+        self.results.push_back(KeptResult { id, query: query.trim().to_string(), duration, result, size });
+        Some(id)
+    }
+
+    pub fn get(&self, id: usize) -> Result<&KeptResult, String> {
+        self.results.iter().find(|r| r.id == id).ok_or_else(|| format!("No kept result {}", id))
+    }
+
+    /// One line per result, the most recent last : id, row count, execution time and query
+    pub fn lines(&self) -> Vec<String> {
+        self.results.iter().map(|r| {
+            let query = r.query.split_whitespace().collect::<Vec<&str>>().join(" ");
+            let mut shown: String = query.chars().take(QUERY_WIDTH).collect();
+            if shown.len() < query.len() {
+                shown.push_str("...");
+            }
+            format!("{:>4}  {:>8} rows  {:>12}  {}", r.id, r.result.rows.len(), format_duration(r.duration), shown)
+        }).collect()
+    }
+}
+
+/// Like psql's `\timing`
+pub fn format_duration(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Row counts and how the rows differ, ignoring their order
+pub fn compare(a: &KeptResult, b: &KeptResult) -> Vec<String> {
+    let mut lines: Vec<String> = [a, b].iter().map(|r| {
+        format!("Result {} : {} rows, {} columns, {}", r.id, r.result.rows.len(), r.result.columns.len(), format_duration(r.duration))
+    }).collect();
+    let columns = |r: &QueryResult| r.columns.iter().map(|c| (c.name.clone(), c.type_.clone())).collect::<Vec<_>>();
+    if columns(&a.result) != columns(&b.result) {
+        lines.push(String::from("The columns differ."));
+        return lines;
+    }
+    let mut counts: HashMap<&Vec<Option<String>>, isize> = HashMap::new();
+    for row in &a.result.rows {
+        *counts.entry(row).or_default() += 1;
+    }
+    for row in &b.result.rows {
+        *counts.entry(row).or_default() -= 1;
+    }
+    let only_a: isize = counts.values().filter(|&&n| n > 0).sum();
+    let only_b: isize = -counts.values().filter(|&&n| n < 0).sum::<isize>();
+    lines.push(if only_a == 0 && only_b == 0 {
+        if a.result.rows == b.result.rows { String::from("Same rows.") } else { String::from("Same rows, in another order.") }
+    } else {
+        format!("{} rows only in {}, {} rows only in {}.", only_a, a.id, only_b, b.id)
+    });
+    lines
+}
+
+/// Two results side by side, a first column telling what happened to the row :
+/// `+` added, `-` removed, `~` changed or `=` the same
+#[derive(Debug)]
+pub struct Diff {
+    pub result: QueryResult,
+    /// Row and column of the cells that differ
+    pub changed: HashSet<(usize, usize)>,
+    pub summary: String,
+}
+
+/// Rows are matched on the values of the `keys` columns, or by position without keys.
+/// Both results need the same column names.
+pub fn diff(a: &KeptResult, b: &KeptResult, keys: &[String]) -> Result<Diff, String> {
+    let names = |r: &QueryResult| r.columns.iter().map(|c| c.name.clone()).collect::<Vec<String>>();
+    if names(&a.result) != names(&b.result) {
+        return Err(format!("Results {} and {} have different columns", a.id, b.id));
+    }
+    let key_columns = keys.iter().map(|k| {
+        a.result.columns.iter().position(|c| &c.name == k).ok_or_else(|| format!("No column {} in the results", k))
+    }).collect::<Result<Vec<usize>, String>>()?;
+    let (rows_a, rows_b) = (&a.result.rows, &b.result.rows);
+
+    // Rows of a and b, None for a row only in the other result
+    let pairs: Vec<(Option<usize>, Option<usize>)> = if key_columns.is_empty() {
+        (0..max(rows_a.len(), rows_b.len())).map(|i| ((i < rows_a.len()).then_some(i), (i < rows_b.len()).then_some(i))).collect()
+    } else {
+        let key = |row: &Vec<Option<String>>| key_columns.iter().map(|&c| row[c].clone()).collect::<Vec<Option<String>>>();
+        let mut in_b: HashMap<Vec<Option<String>>, VecDeque<usize>> = HashMap::new();
+        for (j, row) in rows_b.iter().enumerate() {
+            in_b.entry(key(row)).or_default().push_back(j);
+        }
+        let mut matched = vec![false; rows_b.len()];
+        let mut pairs: Vec<(Option<usize>, Option<usize>)> = rows_a.iter().enumerate().map(|(i, row)| {
+            let j = in_b.get_mut(&key(row)).and_then(VecDeque::pop_front);
+            if let Some(j) = j {
+                matched[j] = true;
+            }
+            (Some(i), j)
+        }).collect();
+        pairs.extend((0..rows_b.len()).filter(|&j| !matched[j]).map(|j| (None, Some(j))));
+        pairs
+    };
+
+    let width = a.result.columns.len();
+    let mut changed = HashSet::new();
+    let mut counts = [0; 4];
+    let rows: Vec<Vec<Option<String>>> = pairs.iter().enumerate().map(|(row, &(i, j))| {
+        let side = |r: Option<&Vec<Option<String>>>| r.cloned().unwrap_or_else(|| vec![Some(String::new()); width]);
+        let (values_a, values_b) = (side(i.map(|i| &rows_a[i])), side(j.map(|j| &rows_b[j])));
+        let (status, columns): (usize, Vec<usize>) = match (i, j) {
+            (None, _) => (0, (width + 1..=2 * width).collect()),
+            (_, None) => (1, (1..=width).collect()),
+            _ => {
+                let different: Vec<usize> = (0..width).filter(|&c| values_a[c] != values_b[c]).collect();
+                let status = if different.is_empty() { 3 } else { 2 };
+                (status, different.iter().flat_map(|c| vec![c + 1, c + 1 + width]).collect())
+            },
+        };
+        counts[status] += 1;
+        changed.extend(columns.into_iter().map(|c| (row, c)));
+        std::iter::once(Some(["+", "-", "~", "="][status].to_string())).chain(values_a).chain(values_b).collect()
+    }).collect();
+
+    let column = |name: String| ResultColumn { name, max_size: 0, type_: Type::TEXT };
+    let mut columns: Vec<ResultColumn> = std::iter::once(column(String::new()))
+        .chain([a, b].iter().flat_map(|r| r.result.columns.iter().map(move |c| column(format!("{}.{}", r.id, c.name)))))
+        .collect();
+    for (i, c) in columns.iter_mut().enumerate() {
+        let values = rows.iter().map(|row| row[i].as_ref().map_or(4, String::len));
+        c.max_size = values.chain(std::iter::once(c.name.len())).max().unwrap_or(0);
+    }
+    Ok(Diff {
+        result: QueryResult { columns, rows },
+        changed,
+        summary: format!("{} added, {} removed, {} changed, {} the same.", counts[0], counts[1], counts[2], counts[3]),
+    })
+}
+
+/// Bytes of the values and column names
+fn _size(result: &QueryResult) -> usize {
+    let values: usize = result.rows.iter().flatten().map(|v| v.as_ref().map_or(0, String::len)).sum();
+    values + result.columns.iter().map(|c| c.name.len()).sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use postgres_types::Type;
+    use crate::sql::ResultColumn;
+
+    fn _result(values: &[&str]) -> QueryResult {
+        QueryResult {
+            columns: vec![ResultColumn { name: String::from("v"), max_size: 0, type_: Type::TEXT }],
+            rows: values.iter().map(|v| vec![Some(v.to_string())]).collect(),
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let mut results = ResultHistory::new(2, 10);
+        assert_eq!(results.push("select 1", Duration::from_millis(1), _result(&["a"])), Some(1));
+        assert_eq!(results.push("select 2", Duration::from_millis(1), _result(&["b"])), Some(2));
+        assert_eq!(results.push("select 3", Duration::from_millis(1), _result(&["c"])), Some(3));
+        assert!(results.get(1).is_err());
+        assert_eq!(results.push("select 4", Duration::from_millis(1), _result(&["too big!"])), Some(4));
+        assert_eq!(results.lines().len(), 1);
+        assert_eq!(results.push("select 5", Duration::from_millis(1), _result(&["much too big"])), None);
+        assert_eq!(results.get(4).unwrap().query, "select 4");
+    }
+
+    #[test]
+    fn comparison() {
+        let mut results = ResultHistory::new(3, 100);
+        for values in &[&["a", "b", "b"][..], &["b", "a", "b"][..], &["a", "c"][..]] {
+            results.push("", Duration::from_millis(1), _result(values));
+        }
+        let compare_ids = |a, b| compare(results.get(a).unwrap(), results.get(b).unwrap()).pop().unwrap();
+        assert_eq!(compare_ids(1, 1), "Same rows.");
+        assert_eq!(compare_ids(1, 2), "Same rows, in another order.");
+        assert_eq!(compare_ids(1, 3), "2 rows only in 1, 1 rows only in 3.");
+    }
+
+    #[test]
+    fn diffs() {
+        let mut results = ResultHistory::new(2, 100);
+        results.push("", Duration::from_millis(1), _result(&["a", "b", "c"]));
+        results.push("", Duration::from_millis(1), _result(&["a", "d"]));
+        let (a, b) = (results.get(1).unwrap(), results.get(2).unwrap());
+        let status = |d: &Diff| d.result.rows.iter().map(|r| r[0].clone().unwrap()).collect::<Vec<String>>().join("");
+
+        let positional = diff(a, b, &[]).unwrap();
+        assert_eq!(status(&positional), "=~-");
+        assert!(positional.changed.contains(&(1, 1)) && positional.changed.contains(&(1, 2)));
+        assert_eq!(positional.summary, "0 added, 1 removed, 1 changed, 1 the same.");
+
+        let by_key = diff(a, b, &[String::from("v")]).unwrap();
+        assert_eq!(status(&by_key), "=--+");
+        assert_eq!(by_key.result.columns[1].name, "1.v");
+        assert!(diff(a, b, &[String::from("x")]).is_err());
+    }
+}
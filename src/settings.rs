@@ -0,0 +1,119 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path;
+
+const SETTINGS_FILE: &str = "rpsql/config.json";
+const DEFAULT_PROMPT1: &str = "$> ";
+const DEFAULT_PROMPT2: &str = "%R> ";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EditMode {
+    #[default]
+    Emacs,
+    Vi,
+}
+
+/// When query results are shown in a pager, like psql's `\pset pager`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PagerMode {
+    /// Only when the result does not fit the terminal
+    #[default]
+    On,
+    Off,
+    Always,
+}
+
+/// User settings, read from `rpsql/config.json` in the config directory. Every field is optional.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub edit_mode: EditMode,
+    /// Prompt of the first line, with psql's `%` escapes (`%n`, `%m`, `%/`, `%#`...) and `%P` for the profile name
+    pub prompt1: String,
+    /// Prompt of the following lines of a multi-line query
+    pub prompt2: String,
+    /// Changes to the default key bindings : `{"input": {"Ctrl-p": "history-prev", "PageUp": null}, "pager": {...}}`
+    pub bindings: HashMap<String, HashMap<String, Option<String>>>,
+    /// Mouse capture, for the wheel and clicks in the pager and the input line.
+    /// Without it the terminal's own text selection works.
+    pub mouse: bool,
+    /// Columns staying at the left of the pager when scrolling right, `f` in the pager changes it
+    pub frozen_columns: usize,
+    pub pager: PagerMode,
+    /// Uses `$PSQL_PAGER`, `$PAGER` or `less -S` instead of the built-in pager
+    pub external_pager: bool,
+    /// Results kept in memory for `\results`
+    pub kept_results: usize,
+    /// Size of the values of the kept results, in megabytes
+    pub kept_results_mb: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            edit_mode: EditMode::default(),
+            prompt1: DEFAULT_PROMPT1.to_string(),
+            prompt2: DEFAULT_PROMPT2.to_string(),
+            bindings: HashMap::new(),
+            mouse: true,
+            frozen_columns: 0,
+            pager: PagerMode::default(),
+            external_pager: false,
+            kept_results: 10,
+            kept_results_mb: 64,
+        }
+    }
+}
+
+impl Settings {
+    /// Changes a setting for the session, like psql's `\pset`, and describes its new value
+    pub fn pset(&mut self, option: &str, value: Option<&str>) -> Result<String, String> {
+        match option {
+            "pager" => {
+                self.pager = match value {
+                    // Toggles, like in psql
+                    None if self.pager == PagerMode::Off => PagerMode::On,
+                    None => PagerMode::Off,
+                    Some("on") => PagerMode::On,
+                    Some("off") => PagerMode::Off,
+                    Some("always") => PagerMode::Always,
+                    Some(v) => return Err(format!("\\pset: allowed pager values are on, off, always, not \"{}\"", v)),
+                };
+                Ok(match self.pager {
+                    PagerMode::On => "Pager is used for long output.",
+                    PagerMode::Off => "Pager usage is off.",
+                    PagerMode::Always => "Pager is always used.",
+                }.to_string())
+            },
+            _ => Err(format!("\\pset: unknown option: {}", option)),
+        }
+    }
+}
+
+pub fn load() -> Settings {
+    let filename = _get_settings_file();
+    match fs::read_to_string(&filename) {
+        Ok(contents) => {
+            serde_json::from_str(&contents).unwrap_or_else(|e| {
+                eprintln!("Error in settings file {} : {}, using default settings", filename.to_str().unwrap(), e);
+                Settings::default()
+            })
+        },
+        Err(e) => {
+            if e.kind() != io::ErrorKind::NotFound {
+                eprintln!("Could not read settings file {} : {}", filename.to_str().unwrap(), e);
+            }
+            Settings::default()
+        }
+    }
+}
+
+fn _get_settings_file() -> path::PathBuf {
+    let mut p = dirs::config_dir().unwrap_or_default();
+    p.push(SETTINGS_FILE);
+    p
+}
@@ -2,12 +2,13 @@ use crate::connection_options::ConnectionOptions;
 pub use postgres::Client;
 use postgres::{NoTls, SimpleQueryMessage, SimpleQueryRow, Statement};
 use postgres_types::Type;
+use crate::lexer::{self, Context};
 
 #[derive(Debug)]
-struct ResultColumn {
-    name: String,
-    max_size: usize,
-    type_: Type,
+pub struct ResultColumn {
+    pub name: String,
+    pub max_size: usize,
+    pub type_: Type,
 }
 
 impl ResultColumn {
@@ -20,7 +21,124 @@ impl ResultColumn {
     }
 }
 
+/// Types whose values are numbers in text format
+pub fn is_numeric(type_: &Type) -> bool {
+    matches!(*type_, Type::INT2 | Type::INT4 | Type::INT8 | Type::FLOAT4 | Type::FLOAT8 | Type::NUMERIC | Type::OID)
+}
+
 const ADDITIONAL_SPACES: usize = 2;
+/// Lines of the header in the output of `QueryResult::lines`
+pub const HEADER_LINES: usize = 2;
+
+/// Rows of a query, values are as sent by the server in text format, `None` for NULL
+#[derive(Debug)]
+pub struct QueryResult {
+    pub columns: Vec<ResultColumn>,
+    pub rows: Vec<Vec<Option<String>>>,
+}
+
+impl QueryResult {
+    /// The result as a table : the header, its separator, then one line per row
+    pub fn lines(&self) -> Vec<String> {
+        let mut buffer = Vec::new();
+        _display_header(&self.columns, &mut buffer);
+
+        for row in &self.rows {
+            let mut tmp = String::new();
+            for (val, c) in row.iter().zip(&self.columns) {
+                let val = val.as_deref().unwrap_or("None");
+                tmp.push_str(format!("{:^width$}|", val, width = c.max_size + ADDITIONAL_SPACES).as_str());
+            }
+            buffer.push(tmp);
+        }
+        buffer
+    }
+
+    /// `[start, end)` of every column in `lines`, `end` being the position of its separator
+    pub fn column_bounds(&self) -> Vec<(usize, usize)> {
+        let mut start = 0;
+        self.columns.iter().map(|c| {
+            let bounds = (start, start + c.max_size + ADDITIONAL_SPACES);
+            start = bounds.1 + 1;
+            bounds
+        }).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionStatus {
+    Idle,
+    InTransaction,
+    Failed,
+}
+
+/// How far a query went
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueryOutcome {
+    Succeeded,
+    /// Refused before anything ran, e.g. a syntax error
+    Rejected,
+    /// An error while the statements ran
+    Failed,
+}
+
+/// The message of a failed query, and whether it failed before or while running
+#[derive(Debug)]
+pub struct QueryError {
+    pub outcome: QueryOutcome,
+    pub message: String,
+}
+
+impl TransactionStatus {
+    /// The status once `query` ran, from the statements it contains : the client does not expose
+    /// the status sent by the server with ReadyForQuery, and asking the server would run a statement
+    /// inside the user's transaction.
+    pub fn after(self, query: &str, outcome: QueryOutcome) -> TransactionStatus {
+        let statements = _statements_words(query);
+        match outcome {
+            // Nothing ran, but an error inside a transaction block aborts it
+            QueryOutcome::Rejected if self == TransactionStatus::Idle => return self,
+            QueryOutcome::Rejected => return TransactionStatus::Failed,
+            QueryOutcome::Failed => {
+                // Which statement failed is unknown, any transaction block open during the query is aborted
+                let opens = statements.iter().any(|words| matches!(words.first().map(String::as_str), Some("begin") | Some("start")));
+                return if self != TransactionStatus::Idle || opens { TransactionStatus::Failed } else { TransactionStatus::Idle };
+            },
+            QueryOutcome::Succeeded => {},
+        }
+        statements.iter().fold(self, |status, words| {
+            let words: Vec<&str> = words.iter().map(String::as_str).collect();
+            match words[..] {
+                ["begin", ..] | ["start", "transaction", ..] => TransactionStatus::InTransaction,
+                ["commit", .., "and", "chain"] | ["rollback", .., "and", "chain"] | ["end", .., "and", "chain"] => TransactionStatus::InTransaction,
+                // Back to a savepoint, the transaction goes on
+                ["rollback", "to", ..] | ["rollback", "transaction", "to", ..] | ["rollback", "work", "to", ..] => {
+                    if status == TransactionStatus::Idle { status } else { TransactionStatus::InTransaction }
+                },
+                ["commit", ..] | ["end", ..] | ["rollback", ..] | ["abort", ..] | ["prepare", "transaction", ..] => TransactionStatus::Idle,
+                _ => status,
+            }
+        })
+    }
+}
+
+/// Lowercase words of every statement, without literals and comments
+fn _statements_words(query: &str) -> Vec<Vec<String>> {
+    let text: Vec<char> = query.chars().collect();
+    let states = lexer::scan(&text);
+    let mut statements = vec![String::new()];
+    for (c, state) in text.iter().zip(&states) {
+        match (state.context, c) {
+            (Context::Code, ';') => statements.push(String::new()),
+            (Context::Code, c) if c.is_alphanumeric() || *c == '_' => statements.last_mut().unwrap().extend(c.to_lowercase()),
+            _ => statements.last_mut().unwrap().push(' '),
+        }
+    }
+    statements.iter()
+        .map(|s| s.split_whitespace().map(String::from).collect::<Vec<String>>())
+        .filter(|words| !words.is_empty())
+        .collect()
+}
 
 pub fn try_connect(connection_options: &ConnectionOptions, password: Option<String>) -> Result<Client, String> {
     let connection_string = if let Some(password) = password {
@@ -51,40 +169,79 @@ ORDER BY name
 ;
 ";
 
-pub fn handle_query(mut client: &mut Client, query: &str) -> Result<Vec<String>, String> {
+const FUNCTION_TEMPLATE: &str = "CREATE FUNCTION ( )
+ RETURNS 
+ LANGUAGE 
+ -- common options:  IMMUTABLE  STABLE  STRICT  SECURITY DEFINER
+AS $function$
+
+$function$
+";
+
+const VIEW_TEMPLATE: &str = "CREATE VIEW  AS
+ SELECT 
+  -- something...
+";
+
+/// `CREATE OR REPLACE FUNCTION` statement for `name` (with its argument types if overloaded), or a template
+pub fn function_definition(client: &mut Client, name: Option<&str>) -> Result<String, String> {
+    match name {
+        None => Ok(FUNCTION_TEMPLATE.to_string()),
+        Some(name) => {
+            let cast = if name.contains('(') { "regprocedure" } else { "regproc" };
+            let query = format!("SELECT pg_get_functiondef($1::text::{})", cast);
+            _get_definition(client, &query, name)
+        }
+    }
+}
+
+/// `CREATE OR REPLACE VIEW` statement for `name`, or a template
+pub fn view_definition(client: &mut Client, name: Option<&str>) -> Result<String, String> {
+    match name {
+        None => Ok(VIEW_TEMPLATE.to_string()),
+        Some(name) => {
+            let query = "SELECT 'CREATE OR REPLACE VIEW ' || $1::text::regclass::text || ' AS' || E'\\n' || pg_get_viewdef($1::text::regclass, true)";
+            _get_definition(client, query, name)
+        }
+    }
+}
+
+fn _get_definition(client: &mut Client, query: &str, name: &str) -> Result<String, String> {
+    match client.query_one(query, &[&name]) {
+        Ok(row) => Ok(row.get::<_, String>(0)),
+        Err(e) => Err(format!("Error while getting definition of {} : {}", name, e)),
+    }
+}
+
+pub fn is_superuser(client: &mut Client) -> bool {
+    client.query_one("SELECT current_setting('is_superuser') = 'on'", &[])
+        .map(|row| row.get::<_, bool>(0))
+        .unwrap_or(false)
+}
+
+pub fn handle_query(mut client: &mut Client, query: &str) -> Result<QueryResult, QueryError> {
     let mut query = query;
     if query.starts_with('\\') {
         query = BUILTIN_DESCRIBE_ALL;
     }
     let res_prepare = _prepare_query(&mut client, query);
-    if let Err(e) = res_prepare {
-        return Err(e);
+    if let Err(message) = res_prepare {
+        return Err(QueryError { outcome: QueryOutcome::Rejected, message });
     }
     let res_prepare = res_prepare.unwrap();
 
     let res_execute = _get_result_rows(&mut client, &query);
-    if let Err(e) = res_execute {
-        return Err(e);
+    if let Err(message) = res_execute {
+        return Err(QueryError { outcome: QueryOutcome::Failed, message });
     }
 
     let rows = res_execute.unwrap();
     let columns = _get_columns_from_result(&res_prepare, &rows);
+    let rows = rows.iter().map(|row| {
+        (0..columns.len()).map(|i| row.get(i).map(String::from)).collect()
+    }).collect();
 
-    let mut buffer = Vec::new();
-    _display_header(&columns, &mut buffer);
-
-    for row in &rows {
-        let mut tmp = String::new();
-        for (i, c) in columns.iter().enumerate() {
-            let val = row.get(i).unwrap_or_else(|| {
-                "None"
-            });
-            tmp.push_str(format!("{:^width$}|", val, width = c.max_size + ADDITIONAL_SPACES).as_str());
-        }
-        buffer.push(tmp);
-    }
-
-    Ok(buffer)
+    Ok(QueryResult { columns, rows })
 }
 
 fn _prepare_query(client: &mut Client, query: &str) -> Result<Statement, String> {
@@ -145,3 +302,28 @@ fn _display_header(columns: &[ResultColumn], buffer: &mut Vec<String>) {
     }
     buffer.push(tmp);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transaction_status() {
+        let idle = TransactionStatus::Idle;
+        let (ok, rejected, failed) = (QueryOutcome::Succeeded, QueryOutcome::Rejected, QueryOutcome::Failed);
+        assert_eq!(idle.after("BEGIN; SET TRANSACTION ISOLATION LEVEL SERIALIZABLE;", ok), TransactionStatus::InTransaction);
+        assert_eq!(idle.after("begin; select 1; commit", ok), TransactionStatus::Idle);
+        assert_eq!(idle.after("select 'begin' -- begin\n", ok), idle);
+        assert_eq!(idle.after("select 1/0", failed), idle);
+        assert_eq!(idle.after("begin; select 1/0;", failed), TransactionStatus::Failed);
+        // A syntax error, the begin did not run
+        assert_eq!(idle.after("begin; selec 1;", rejected), idle);
+        let open = TransactionStatus::InTransaction;
+        assert_eq!(open.after("select 1/0", failed), TransactionStatus::Failed);
+        assert_eq!(open.after("selec 1", rejected), TransactionStatus::Failed);
+        assert_eq!(TransactionStatus::Failed.after("rollback to savepoint s", ok), open);
+        assert_eq!(TransactionStatus::Failed.after("ROLLBACK", ok), idle);
+        assert_eq!(open.after("commit and chain", ok), open);
+        assert_eq!(open.after("commit and no chain", ok), idle);
+    }
+}
@@ -0,0 +1,38 @@
+use std::io::Write;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Asks the terminal to put `text` in the clipboard with the OSC 52 sequence,
+/// it goes through SSH and needs no clipboard program
+pub fn copy(text: &str) {
+    print!("\x1b]52;c;{}\x07", _base64(text.as_bytes()));
+    std::io::stdout().flush().unwrap();
+}
+
+fn _base64(bytes: &[u8]) -> String {
+    let mut res = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64() {
+        assert_eq!(_base64(b""), "");
+        assert_eq!(_base64(b"f"), "Zg==");
+        assert_eq!(_base64(b"fo"), "Zm8=");
+        assert_eq!(_base64(b"foobar"), "Zm9vYmFy");
+    }
+}
@@ -1,14 +1,80 @@
 pub use termion::event::{Event, Key, MouseEvent};
 
+pub const BRACKETED_PASTE_ON: &str = "\x1b[?2004h";
+pub const BRACKETED_PASTE_OFF: &str = "\x1b[?2004l";
+/// Button presses and the wheel, in the SGR encoding which has no limit on coordinates
+pub const MOUSE_ON: &str = "\x1b[?1000h\x1b[?1006h";
+pub const MOUSE_OFF: &str = "\x1b[?1006l\x1b[?1000l";
+const ESC: u8 = 0x1B;
+/// Longest escape sequence waited for, anything longer is not a key
+const MAX_SEQUENCE_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum KeyEvent {
     Key(Key),
     Ctrl(Key),
     Alt(Key),
+    Shift(Key),
+    /// Several modifiers at once
+    Combo(Modifiers, Key),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
 }
 
+impl Modifiers {
+    /// From the `1 + bits` parameter of xterm and kitty sequences, super and meta are ignored
+    fn from_param(param: u32) -> Modifiers {
+        let bits = param.saturating_sub(1);
+        Modifiers {
+            shift: bits & 1 != 0,
+            alt: bits & 2 != 0,
+            ctrl: bits & 4 != 0,
+        }
+    }
+}
+
+impl KeyEvent {
+    pub fn new(key: Key, modifiers: Modifiers) -> KeyEvent {
+        match (modifiers.ctrl, modifiers.alt, modifiers.shift) {
+            (false, false, false) => KeyEvent::Key(key),
+            (true, false, false) => KeyEvent::Ctrl(key),
+            (false, true, false) => KeyEvent::Alt(key),
+            (false, false, true) => KeyEvent::Shift(key),
+            _ => KeyEvent::Combo(modifiers, key),
+        }
+    }
+
+    pub fn split(&self) -> (Modifiers, Key) {
+        let none = Modifiers::default();
+        match *self {
+            KeyEvent::Key(k) => (none, k),
+            KeyEvent::Ctrl(k) => (Modifiers { ctrl: true, ..none }, k),
+            KeyEvent::Alt(k) => (Modifiers { alt: true, ..none }, k),
+            KeyEvent::Shift(k) => (Modifiers { shift: true, ..none }, k),
+            KeyEvent::Combo(m, k) => (m, k),
+        }
+    }
+
+    fn with_alt(&self) -> KeyEvent {
+        let (modifiers, key) = self.split();
+        KeyEvent::new(key, Modifiers { alt: true, ..modifiers })
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub enum TrueEvent {
     KeyEvent(KeyEvent),
     MouseEvent(MouseEvent),
+    /// Bracketed paste : every key until `PasteEnd` is pasted text
+    PasteStart,
+    PasteEnd,
+    /// The terminal window size changed
+    Resize,
     Unsupported(Vec<u8>),
 }
 
@@ -23,19 +89,314 @@ impl TrueEvent {
                 }
             },
             Event::Mouse(m) => TrueEvent::MouseEvent(m),
-            Event::Unsupported(v) => {
-                if v == [27, 91, 49, 59, 53, 67]  || v == [27, 79, 99] { // ctrl + right
-                    TrueEvent::KeyEvent(KeyEvent::Ctrl(Key::Right))
-                } else if v == [27, 91, 49, 59, 53, 68]  || v == [27, 79, 100] { // ctrl + left
-                    TrueEvent::KeyEvent(KeyEvent::Ctrl(Key::Left))
-                } else if v  == [27, 91, 49, 59, 53, 65] || v == [27, 79, 97] { // ctrl + up
-                    TrueEvent::KeyEvent(KeyEvent::Ctrl(Key::Up))
-                } else if v  == [27, 91, 49, 59, 53, 66] || v == [27, 79, 98] { // ctrl + down
-                    TrueEvent::KeyEvent(KeyEvent::Ctrl(Key::Down))
-                } else {
-                    TrueEvent::Unsupported(v)
-                }
-            }
+            Event::Unsupported(v) => TrueEvent::Unsupported(v),
         }
     }
 }
+
+/// Decodes the event at the start of `bytes`, with the number of bytes it takes.
+/// `None` means the event is incomplete and more bytes are needed.
+///
+/// Escape sequences are decoded here rather than by termion, which only knows a few of them :
+/// xterm style modifiers (`ESC [ 1 ; 5 C`), rxvt (`ESC [ a`, `ESC O a`, `ESC [ 3 ^`), the linux
+/// console (`ESC [ [ A`), xterm modifyOtherKeys (`ESC [ 27 ; 5 ; 97 ~`), the kitty keyboard
+/// protocol (`ESC [ 97 ; 5 u`) and Alt sent as an escape prefix by screen and tmux.
+pub fn decode(bytes: &[u8]) -> Option<(TrueEvent, usize)> {
+    if bytes.first() != Some(&ESC) {
+        return _decode_char(bytes);
+    }
+    match bytes.get(1) {
+        // Nothing followed in the same read, it is the escape key and not a sequence
+        None => Some((_key(KeyEvent::Key(Key::Esc)), 1)),
+        Some(b'[') => _decode_csi(bytes),
+        Some(b'O') => _decode_ss3(bytes),
+        Some(_) => {
+            let (event, len) = match bytes[1] {
+                ESC => decode(&bytes[1..])?,
+                _ => _decode_char(&bytes[1..])?,
+            };
+            let event = match event {
+                TrueEvent::KeyEvent(k) => _key(k.with_alt()),
+                _ => TrueEvent::Unsupported(bytes[..len + 1].to_vec()),
+            };
+            Some((event, len + 1))
+        },
+    }
+}
+
+fn _key(k: KeyEvent) -> TrueEvent {
+    TrueEvent::KeyEvent(k)
+}
+
+/// A character, control characters are reported as Ctrl by termion
+fn _decode_char(bytes: &[u8]) -> Option<(TrueEvent, usize)> {
+    let first = *bytes.first()?;
+    let len = match first {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => 1,
+    };
+    if bytes.len() < len {
+        return None;
+    }
+    let event = match termion::event::parse_event(first, &mut bytes[1..len].iter().map(|b| Ok(*b))) {
+        Ok(e) => TrueEvent::from_termion_event(e),
+        Err(_) => TrueEvent::Unsupported(bytes[..len].to_vec()),
+    };
+    Some((event, len))
+}
+
+/// `ESC [ parameters final`
+fn _decode_csi(bytes: &[u8]) -> Option<(TrueEvent, usize)> {
+    match bytes.get(2)? {
+        // X10 mouse : three raw bytes follow
+        b'M' => {
+            let seq = bytes.get(..6)?;
+            return Some((_termion_sequence(seq), seq.len()));
+        },
+        // Linux console F1 to F5
+        b'[' => {
+            let event = match bytes.get(3)? {
+                c @ b'A'..=b'E' => _key(KeyEvent::Key(Key::F(1 + c - b'A'))),
+                _ => TrueEvent::Unsupported(bytes[..4].to_vec()),
+            };
+            return Some((event, 4));
+        },
+        _ => {},
+    }
+
+    // rxvt ends shifted keys with `$`, which is otherwise an intermediate byte
+    let end = match bytes[2..].iter().position(|&b| !(0x20..=0x3F).contains(&b) || b == b'$') {
+        Some(p) => p + 2,
+        None if bytes.len() < MAX_SEQUENCE_LEN => return None,
+        None => return Some((TrueEvent::Unsupported(bytes.to_vec()), bytes.len())),
+    };
+    let seq = &bytes[..=end];
+    let params = &bytes[2..end];
+    let last = bytes[end];
+    if !(0x24..=0x7E).contains(&last) {
+        // Interrupted by a control character, which is left for the next event
+        return Some((TrueEvent::Unsupported(bytes[..end].to_vec()), end));
+    }
+
+    let event = match (params.first(), last) {
+        (Some(b'<'), b'M' | b'm') | (Some(b'0'..=b'9'), b'M') => Some(_termion_sequence(seq)),
+        _ => _csi_event(params, last),
+    };
+    Some((event.unwrap_or_else(|| TrueEvent::Unsupported(seq.to_vec())), seq.len()))
+}
+
+fn _csi_event(params: &[u8], last: u8) -> Option<TrueEvent> {
+    let params = _parse_params(params)?;
+    let param = |i: usize, j: usize| params.get(i).and_then(|p: &Vec<Option<u32>>| p.get(j).copied().flatten());
+    let modifiers = Modifiers::from_param(param(1, 0).unwrap_or(1));
+
+    let key = match last {
+        b'A'..=b'D' | b'H' | b'F' | b'P'..=b'S' | b'Z' => _letter_key(last)?,
+        b'a'..=b'd' => return Some(_key(KeyEvent::Shift(_letter_key(last.to_ascii_uppercase())?))),
+        b'~' => match param(0, 0)? {
+            200 => return Some(TrueEvent::PasteStart),
+            201 => return Some(TrueEvent::PasteEnd),
+            27 => return _char_key(param(2, 0)?, None, modifiers),
+            n => _tilde_key(n)?,
+        },
+        b'^' | b'$' | b'@' => {
+            let modifiers = Modifiers {
+                ctrl: last != b'$',
+                shift: last != b'^',
+                alt: false,
+            };
+            return Some(_key(KeyEvent::new(_tilde_key(param(0, 0)?)?, modifiers)));
+        },
+        b'u' => {
+            // Key releases are only sent when asked for
+            if param(1, 1) == Some(3) {
+                return None;
+            }
+            return _char_key(param(0, 0)?, param(0, 1), modifiers);
+        },
+        _ => return None,
+    };
+    Some(_key(KeyEvent::new(key, modifiers)))
+}
+
+/// `ESC O final`, with modifiers as parameters for some terminals
+fn _decode_ss3(bytes: &[u8]) -> Option<(TrueEvent, usize)> {
+    let end = 2 + bytes[2..].iter().position(|b| !b.is_ascii_digit() && *b != b';')?;
+    let seq = &bytes[..=end];
+    let params = _parse_params(&bytes[2..end]);
+    let modifiers = match params.as_deref() {
+        Some([p]) | Some([_, p]) => Modifiers::from_param(p.first().copied().flatten().unwrap_or(1)),
+        _ => Modifiers::default(),
+    };
+    let event = match bytes[end] {
+        b'M' => Some(KeyEvent::new(Key::Char('\n'), modifiers)),
+        // rxvt Ctrl arrows
+        c @ b'a'..=b'd' => _letter_key(c.to_ascii_uppercase()).map(KeyEvent::Ctrl),
+        c => _letter_key(c).map(|k| KeyEvent::new(k, modifiers)),
+    };
+    Some((event.map(_key).unwrap_or_else(|| TrueEvent::Unsupported(seq.to_vec())), seq.len()))
+}
+
+/// Semicolon separated parameters, each with colon separated sub parameters which can be empty
+fn _parse_params(params: &[u8]) -> Option<Vec<Vec<Option<u32>>>> {
+    let params = std::str::from_utf8(params).ok()?;
+    if params.is_empty() {
+        return Some(Vec::new());
+    }
+    params.split(';').map(|p| {
+        p.split(':').map(|s| match s {
+            "" => Some(None),
+            s => s.parse().ok().map(Some),
+        }).collect()
+    }).collect()
+}
+
+fn _letter_key(c: u8) -> Option<Key> {
+    Some(match c {
+        b'A' => Key::Up,
+        b'B' => Key::Down,
+        b'C' => Key::Right,
+        b'D' => Key::Left,
+        b'H' => Key::Home,
+        b'F' => Key::End,
+        b'P'..=b'S' => Key::F(1 + c - b'P'),
+        b'Z' => Key::BackTab,
+        _ => return None,
+    })
+}
+
+/// Keys of `ESC [ number ~`
+fn _tilde_key(n: u32) -> Option<Key> {
+    Some(match n {
+        1 | 7 => Key::Home,
+        2 => Key::Insert,
+        3 => Key::Delete,
+        4 | 8 => Key::End,
+        5 => Key::PageUp,
+        6 => Key::PageDown,
+        11..=15 => Key::F(n as u8 - 10),
+        17..=21 => Key::F(n as u8 - 11),
+        23..=26 => Key::F(n as u8 - 12),
+        28..=29 => Key::F(n as u8 - 13),
+        31..=34 => Key::F(n as u8 - 14),
+        _ => return None,
+    })
+}
+
+/// A key given by its code point, as kitty and modifyOtherKeys do.
+/// Shifted characters are reported as the character itself, like without the protocol.
+fn _char_key(code: u32, shifted: Option<u32>, modifiers: Modifiers) -> Option<TrueEvent> {
+    let key = match code {
+        9 if modifiers.shift => return Some(_key(KeyEvent::new(Key::BackTab, Modifiers { shift: false, ..modifiers }))),
+        13 => Key::Char('\n'),
+        27 => Key::Esc,
+        8 | 127 => Key::Backspace,
+        // Private use area : kitty's own keys like the keypad or lone modifiers
+        0xE000..=0xF8FF => return None,
+        _ => {
+            let c = char::from_u32(code)?;
+            match shifted.and_then(char::from_u32) {
+                Some(s) if modifiers.shift => return Some(_key(KeyEvent::new(Key::Char(s), Modifiers { shift: false, ..modifiers }))),
+                _ if modifiers.shift && c.is_alphabetic() => {
+                    let upper = c.to_uppercase().next().unwrap_or(c);
+                    return Some(_key(KeyEvent::new(Key::Char(upper), Modifiers { shift: false, ..modifiers })));
+                },
+                _ => Key::Char(c),
+            }
+        },
+    };
+    Some(_key(KeyEvent::new(key, modifiers)))
+}
+
+fn _termion_sequence(seq: &[u8]) -> TrueEvent {
+    match termion::event::parse_event(seq[0], &mut seq[1..].iter().map(|b| Ok(*b))) {
+        Ok(e) => TrueEvent::from_termion_event(e),
+        Err(_) => TrueEvent::Unsupported(seq.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _decode(bytes: &[u8]) -> TrueEvent {
+        let (event, len) = decode(bytes).unwrap();
+        assert_eq!(len, bytes.len(), "{:?}", bytes);
+        event
+    }
+
+    fn _mods(ctrl: bool, alt: bool, shift: bool) -> Modifiers {
+        Modifiers { ctrl, alt, shift }
+    }
+
+    #[test]
+    fn xterm_sequences() {
+        assert_eq!(_decode(b"\x1b[A"), _key(KeyEvent::Key(Key::Up)));
+        assert_eq!(_decode(b"\x1b[1;5C"), _key(KeyEvent::Ctrl(Key::Right)));
+        assert_eq!(_decode(b"\x1b[1;2D"), _key(KeyEvent::Shift(Key::Left)));
+        assert_eq!(_decode(b"\x1b[1;3H"), _key(KeyEvent::Alt(Key::Home)));
+        assert_eq!(_decode(b"\x1b[1;6F"), _key(KeyEvent::Combo(_mods(true, false, true), Key::End)));
+        assert_eq!(_decode(b"\x1b[1;8B"), _key(KeyEvent::Combo(_mods(true, true, true), Key::Down)));
+        assert_eq!(_decode(b"\x1b[3;5~"), _key(KeyEvent::Ctrl(Key::Delete)));
+        assert_eq!(_decode(b"\x1b[5~"), _key(KeyEvent::Key(Key::PageUp)));
+        assert_eq!(_decode(b"\x1b[15;2~"), _key(KeyEvent::Shift(Key::F(5))));
+        assert_eq!(_decode(b"\x1b[24~"), _key(KeyEvent::Key(Key::F(12))));
+        assert_eq!(_decode(b"\x1b[1;5P"), _key(KeyEvent::Ctrl(Key::F(1))));
+        assert_eq!(_decode(b"\x1bOQ"), _key(KeyEvent::Key(Key::F(2))));
+        assert_eq!(_decode(b"\x1bOH"), _key(KeyEvent::Key(Key::Home)));
+        assert_eq!(_decode(b"\x1bO5C"), _key(KeyEvent::Ctrl(Key::Right)));
+        assert_eq!(_decode(b"\x1b[Z"), _key(KeyEvent::Key(Key::BackTab)));
+        assert_eq!(_decode(b"\x1b[27;5;13~"), _key(KeyEvent::Ctrl(Key::Char('\n'))));
+        assert_eq!(_decode(b"\x1b[200~"), TrueEvent::PasteStart);
+        assert_eq!(_decode(b"\x1b[201~"), TrueEvent::PasteEnd);
+    }
+
+    #[test]
+    fn rxvt_and_console_sequences() {
+        assert_eq!(_decode(b"\x1bOc"), _key(KeyEvent::Ctrl(Key::Right)));
+        assert_eq!(_decode(b"\x1bOa"), _key(KeyEvent::Ctrl(Key::Up)));
+        assert_eq!(_decode(b"\x1b[d"), _key(KeyEvent::Shift(Key::Left)));
+        assert_eq!(_decode(b"\x1b[7^"), _key(KeyEvent::Ctrl(Key::Home)));
+        assert_eq!(_decode(b"\x1b[8$"), _key(KeyEvent::Shift(Key::End)));
+        assert_eq!(_decode(b"\x1b[3@"), _key(KeyEvent::Combo(_mods(true, false, true), Key::Delete)));
+        assert_eq!(_decode(b"\x1b[[B"), _key(KeyEvent::Key(Key::F(2))));
+    }
+
+    #[test]
+    fn kitty_sequences() {
+        assert_eq!(_decode(b"\x1b[97;5u"), _key(KeyEvent::Ctrl(Key::Char('a'))));
+        assert_eq!(_decode(b"\x1b[97;6u"), _key(KeyEvent::Ctrl(Key::Char('A'))));
+        assert_eq!(_decode(b"\x1b[49:33;2u"), _key(KeyEvent::Key(Key::Char('!'))));
+        assert_eq!(_decode(b"\x1b[13;5u"), _key(KeyEvent::Ctrl(Key::Char('\n'))));
+        assert_eq!(_decode(b"\x1b[127;3u"), _key(KeyEvent::Alt(Key::Backspace)));
+        assert_eq!(_decode(b"\x1b[9;2u"), _key(KeyEvent::Key(Key::BackTab)));
+        assert_eq!(_decode(b"\x1b[27u"), _key(KeyEvent::Key(Key::Esc)));
+        assert_eq!(_decode(b"\x1b[1;5:1C"), _key(KeyEvent::Ctrl(Key::Right)));
+        assert_eq!(_decode(b"\x1b[97;5:3u"), TrueEvent::Unsupported(b"\x1b[97;5:3u".to_vec()));
+        assert_eq!(_decode(b"\x1b[57441u"), TrueEvent::Unsupported(b"\x1b[57441u".to_vec()));
+    }
+
+    #[test]
+    fn escape_prefix() {
+        assert_eq!(_decode(b"\x1bb"), _key(KeyEvent::Alt(Key::Char('b'))));
+        assert_eq!(_decode(b"\x1b\x7f"), _key(KeyEvent::Alt(Key::Backspace)));
+        assert_eq!(_decode(b"\x1b\x1b[C"), _key(KeyEvent::Alt(Key::Right)));
+        assert_eq!(_decode(b"\x1b\x1b[1;5C"), _key(KeyEvent::Combo(_mods(true, true, false), Key::Right)));
+        assert_eq!(_decode(b"\x1b\x1b"), _key(KeyEvent::Alt(Key::Esc)));
+        assert_eq!(_decode(b"\x1b"), _key(KeyEvent::Key(Key::Esc)));
+    }
+
+    #[test]
+    fn characters_and_partial_sequences() {
+        assert_eq!(_decode("é".as_bytes()), _key(KeyEvent::Key(Key::Char('é'))));
+        assert_eq!(_decode(b"\x01"), _key(KeyEvent::Ctrl(Key::Char('a'))));
+        assert_eq!(_decode(b"\r"), _key(KeyEvent::Key(Key::Char('\n'))));
+        assert!(decode(&"é".as_bytes()[..1]).is_none());
+        assert!(decode(b"\x1b[1;5").is_none());
+        assert!(decode(b"\x1bO").is_none());
+        assert_eq!(decode(b"\x1b[Ab"), Some((_key(KeyEvent::Key(Key::Up)), 3)));
+        assert_eq!(_decode(b"\x1b[<0;3;4M"), TrueEvent::MouseEvent(MouseEvent::Press(termion::event::MouseButton::Left, 3, 4)));
+    }
+}
@@ -1,7 +1,17 @@
-use std::io::{stdin, Read};
-use termion::input::TermRead;
+use std::cmp::max;
+use std::io::{Read, Stdout, Write};
+use termion::raw::RawTerminal;
+use unicode_width::UnicodeWidthStr;
 use crate::history::History;
-use crate::ui::event::{TrueEvent};
+use crate::settings::{PagerMode, Settings};
+use crate::editor;
+use crate::pager;
+use crate::prompt::Prompt;
+use crate::sql::QueryResult;
+use crate::results::Diff;
+use crate::ui::event::{TrueEvent, KeyEvent, Key, BRACKETED_PASTE_ON, BRACKETED_PASTE_OFF, MOUSE_ON, MOUSE_OFF};
+use crate::ui::input;
+use crate::ui::keymap::Keymap;
 use crate::ui::text_input::{TextInput, TextInputEvent};
 use crate::ui::text_view::TextView;
 
@@ -12,48 +22,113 @@ pub enum Event {
 }
 
 pub fn init() {
-   print!("{}{}", termion::cursor::Goto(1, 1), termion::clear::All);
+   print!("{}{}{}", termion::cursor::Goto(1, 1), termion::clear::All, BRACKETED_PASTE_ON);
+}
+
+pub fn quit() {
+    print!("{}", BRACKETED_PASTE_OFF);
+    std::io::stdout().flush().unwrap();
 }
 
-#[allow(dead_code)]
 pub fn display_vec(v: &[String]) {
     for s in v {
         display_string(s);
     }
 }
 
-#[allow(dead_code)]
 pub fn display_string(s: &str) {
-    print!("{}\r\n", s);
+    print!("{}\r\n", s.replace('\n', "\r\n"));
 }
 
 pub fn display_error_string(s: &str) {
     eprint!("{}\r\n", s);
 }
 
-#[allow(dead_code)]
-pub fn display_on_alternate_screen(v: &[String]) {
-    let mut text_view = TextView::new(v);
+/// Asks a yes or no question, any key other than `y` means no
+pub fn confirm(question: &str) -> bool {
+    print!("{}", question);
+    std::io::stdout().flush().unwrap();
+    let answer = input::events().find_map(|e| match e {
+        TrueEvent::KeyEvent(KeyEvent::Key(Key::Char(c))) => Some(c),
+        TrueEvent::KeyEvent(_) => Some('n'),
+        _ => None,
+    }).unwrap_or('n');
+    if !answer.is_control() {
+        print!("{}", answer);
+    }
+    print!("\r\n");
+    answer == 'y' || answer == 'Y'
+}
+
+/// Prints `result` below the input, or shows it in a pager depending on `\pset pager` and whether it fits the terminal
+pub fn display_result(result: &QueryResult, stdout: &RawTerminal<Stdout>, settings: &Settings, keymap: &Keymap) {
+    let lines = result.lines();
+    let (width, height) = termion::terminal_size().unwrap_or((80, 24));
+    // One line is left for the next prompt
+    let fits = lines.len() < height as usize && lines.iter().all(|l| l.width() <= width as usize);
+    let paged = match settings.pager {
+        PagerMode::On => !fits,
+        PagerMode::Off => false,
+        PagerMode::Always => true,
+    };
+    if !paged {
+        display_vec(&lines[..]);
+    } else if settings.external_pager {
+        if let Err(e) = pager::page(stdout, &format!("{}\n", lines.join("\n"))) {
+            display_error_string(&format!("Error while paging : {}", e));
+        }
+    } else {
+        display_on_alternate_screen(result, settings, keymap);
+    }
+}
+
+pub fn display_on_alternate_screen(result: &QueryResult, settings: &Settings, keymap: &Keymap) {
+    _run_text_view(TextView::new(result, keymap, settings.frozen_columns), settings);
+}
+
+/// Always in the built-in pager for the highlights, the first column with the status of the rows stays on the left
+pub fn display_diff(diff: &Diff, settings: &Settings, keymap: &Keymap) {
+    let mut text_view = TextView::new(&diff.result, keymap, max(settings.frozen_columns, 1));
+    text_view.mark_changed(diff.changed.clone());
+    _run_text_view(text_view, settings);
+}
+
+fn _run_text_view(mut text_view: TextView, settings: &Settings) {
     text_view.display();
-    let stdin = stdin();
+    _capture_mouse(settings, true);
 
-    for e in stdin.events() {
-        let event = e.unwrap();
-        let true_event = TrueEvent::from_termion_event(event);
+    for true_event in input::events() {
         if !text_view.handle_event(true_event) {
             break;
         }
     }
+    _capture_mouse(settings, false);
+}
+
+/// Only while rpsql reads events, not while an editor runs or results are printed
+fn _capture_mouse(settings: &Settings, on: bool) {
+    if settings.mouse {
+        print!("{}", if on { MOUSE_ON } else { MOUSE_OFF });
+        std::io::stdout().flush().unwrap();
+    }
+}
+
+/// `input` is the initial content of the input line
+pub fn get_input(stdout: &mut termion::raw::RawTerminal<std::io::Stdout>, history: &mut History, settings: &Settings, keymap: &Keymap, prompt: Prompt, input: Vec<char>) -> Event {
+    _capture_mouse(settings, true);
+    let event = _get_input(stdout, history, settings, keymap, prompt, input);
+    _capture_mouse(settings, false);
+    event
 }
 
-pub fn get_input(stdout: &mut termion::raw::RawTerminal<std::io::Stdout>, history: &mut History) -> Event {
-    let stdin = stdin();
-    let mut ti = TextInput::new(stdout);
+fn _get_input(stdout: &mut termion::raw::RawTerminal<std::io::Stdout>, history: &mut History, settings: &Settings, keymap: &Keymap, prompt: Prompt, input: Vec<char>) -> Event {
+    let mut ti = TextInput::new(stdout, settings.edit_mode, prompt, keymap);
+    if !input.is_empty() {
+        ti.set_data(input);
+    }
     let mut buffer_save: Vec<char> = Vec::new();
-    for e in stdin.events() {
-        let event = e.unwrap();
-        let true_event = TrueEvent::from_termion_event(event);
-        let res = ti.handle_event(true_event);
+    for true_event in input::events() {
+        let res = ti.handle_event(true_event, history);
         match res {
             TextInputEvent::HistoryPrev => {
                 if history.current_command() == -1 {
@@ -76,6 +151,30 @@ pub fn get_input(stdout: &mut termion::raw::RawTerminal<std::io::Stdout>, histor
                     ti.set_data(buffer_save.clone());
                 }
             },
+            TextInputEvent::Edit => {
+                let mut data = ti.data();
+                if data.is_empty() {
+                    data = history.last_query().unwrap_or_default();
+                }
+                _capture_mouse(settings, false);
+                let edited = editor::edit_text(stdout, &data.iter().collect::<String>(), None);
+                _capture_mouse(settings, true);
+                match edited {
+                    Ok(text) => {
+                        if editor::is_terminated(&text) {
+                            history.push_and_save(&text.chars().collect::<Vec<char>>());
+                            history.reset_index();
+                            return Event::Buffer(text);
+                        }
+                        ti.set_data(text.chars().collect());
+                    },
+                    Err(e) => {
+                        print!("\r\n");
+                        display_error_string(&format!("Error while editing : {}", e));
+                        ti.reset_position(stdout);
+                    }
+                }
+            },
             TextInputEvent::None => {}
             TextInputEvent::Quit => return Event::Quit,
             TextInputEvent::Buffer(raw, buffer) => {
@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+use std::io;
+use std::os::unix::io::{IntoRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Mutex, Once};
+use crate::ui::event::{self, TrueEvent};
+
+const STDIN: RawFd = 0;
+
+static REGISTER_RESIZE: Once = Once::new();
+static RESIZE_FD: AtomicI32 = AtomicI32::new(-1);
+/// Bytes read from stdin but not decoded yet. Shared by every `Events`, so that what was read ahead
+/// by one of them (e.g. keys typed while a query runs) is not lost when the next one takes over.
+static PENDING: Mutex<VecDeque<u8>> = Mutex::new(VecDeque::new());
+
+/// Terminal events read from stdin. Window size changes (SIGWINCH) are reported as `TrueEvent::Resize`.
+///
+/// termion's own event iterator blocks on stdin and keeps bytes to itself, so it cannot be
+/// interrupted by the signal : stdin and the signal pipe are polled together instead.
+pub struct Events;
+
+pub fn events() -> Events {
+    Events
+}
+
+impl Iterator for Events {
+    type Item = TrueEvent;
+
+    fn next(&mut self) -> Option<TrueEvent> {
+        let mut pending = PENDING.lock().unwrap();
+        if pending.is_empty() {
+            match _wait() {
+                Ok(true) => return Some(TrueEvent::Resize),
+                Ok(false) => {
+                    if !_fill(&mut pending).ok()? {
+                        return None;
+                    }
+                },
+                Err(_) => return None,
+            }
+        }
+
+        loop {
+            if let Some((event, len)) = event::decode(pending.make_contiguous()) {
+                pending.drain(..len);
+                return Some(event);
+            }
+            // Incomplete sequence, the rest of it is on its way
+            if !_fill(&mut pending).unwrap_or(false) {
+                return Some(TrueEvent::Unsupported(pending.drain(..).collect()));
+            }
+        }
+    }
+}
+
+/// Reads what is available on stdin, returns `false` at end of file
+fn _fill(pending: &mut VecDeque<u8>) -> io::Result<bool> {
+    let mut buf = [0u8; 1024];
+    let n = _read(STDIN, &mut buf)?;
+    pending.extend(&buf[..n]);
+    Ok(n > 0)
+}
+
+fn _read(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n >= 0 {
+            return Ok(n as usize);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Blocks until stdin is readable or the window was resized, returns `true` for the latter
+fn _wait() -> io::Result<bool> {
+    let resize_fd = _resize_fd();
+    let mut fds = [
+        libc::pollfd { fd: STDIN, events: libc::POLLIN, revents: 0 },
+        libc::pollfd { fd: resize_fd, events: libc::POLLIN, revents: 0 },
+    ];
+    loop {
+        let n = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+        if n >= 0 {
+            break;
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+
+    if fds[1].revents & libc::POLLIN != 0 {
+        // Several signals can be pending, they all mean the same thing
+        let mut buf = [0u8; 64];
+        while let Ok(n) = _read(resize_fd, &mut buf) {
+            if n < buf.len() {
+                break;
+            }
+        }
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Read end of the pipe written to on SIGWINCH, -1 (ignored by poll) if the handler could not be registered
+fn _resize_fd() -> RawFd {
+    REGISTER_RESIZE.call_once(|| {
+        if let Ok((read, write)) = UnixStream::pair() {
+            if read.set_nonblocking(true).is_ok() && signal_hook::pipe::register(signal_hook::SIGWINCH, write).is_ok() {
+                RESIZE_FD.store(read.into_raw_fd(), Ordering::SeqCst);
+            }
+        }
+    });
+    RESIZE_FD.load(Ordering::SeqCst)
+}
@@ -0,0 +1,269 @@
+use postgres::types::Type;
+use std::cmp::{max, min};
+use termion::style::{Bold, Reset};
+use unicode_width::UnicodeWidthChar;
+use crate::sql::ResultColumn;
+use crate::ui::event::{KeyEvent, Key};
+
+const INDENT: &str = "  ";
+/// Bytes per line of a hex dump
+const HEX_WIDTH: usize = 16;
+
+/// Popup over the pager showing the full value of a cell
+pub struct Inspector {
+    /// Column name and type
+    title: String,
+    /// The formatted value, before wrapping
+    lines: Vec<String>,
+    /// First line shown
+    y: usize,
+}
+
+impl Inspector {
+    pub fn new(column: &ResultColumn, value: Option<&str>) -> Inspector {
+        Inspector {
+            title: format!("{} ({})", column.name, column.type_.name()),
+            lines: format_value(&column.type_, value),
+            y: 0,
+        }
+    }
+
+    /// Returns false when the popup is closed
+    pub fn handle_key(&mut self, key: KeyEvent, max_x: u16, max_y: u16) -> bool {
+        let (_, height) = self._size(max_x, max_y);
+        let last = self._wrapped(max_x).len().saturating_sub(height);
+        match key {
+            KeyEvent::Key(Key::Esc) | KeyEvent::Key(Key::Char('q')) | KeyEvent::Key(Key::Char('\n')) => return false,
+            KeyEvent::Key(Key::Up) | KeyEvent::Key(Key::Char('k')) => self.y = self.y.saturating_sub(1),
+            KeyEvent::Key(Key::Down) | KeyEvent::Key(Key::Char('j')) => self.y += 1,
+            KeyEvent::Key(Key::PageUp) => self.y = self.y.saturating_sub(height),
+            KeyEvent::Key(Key::PageDown) | KeyEvent::Key(Key::Char(' ')) => self.y += height,
+            KeyEvent::Key(Key::Home) | KeyEvent::Key(Key::Char('g')) => self.y = 0,
+            KeyEvent::Key(Key::End) | KeyEvent::Key(Key::Char('G')) => self.y = last,
+            _ => {},
+        }
+        self.y = min(self.y, last);
+        true
+    }
+
+    /// A box in the middle of the screen, drawn over what is already there
+    pub fn render(&self, max_x: u16, max_y: u16) -> String {
+        let (width, height) = self._size(max_x, max_y);
+        let lines = self._wrapped(max_x);
+        let left = (max_x as usize).saturating_sub(width + 4) / 2 + 1;
+        let top = (max_y as usize).saturating_sub(height + 2) / 2 + 1;
+        let goto = |line: usize| termion::cursor::Goto(left as u16, (top + line) as u16);
+
+        let title = _truncate(&format!(" {} ", self.title), width);
+        let position = if lines.len() > height {
+            format!(" {}-{} of {} ", self.y + 1, min(self.y + height, lines.len()), lines.len())
+        } else {
+            String::new()
+        };
+        let fill = (width + 2).saturating_sub(_width(&title) + position.chars().count());
+        let mut res = format!("{}┌{}{}{}{}{}┐", goto(0), Bold, title, Reset, "─".repeat(fill), position);
+        for i in 0..height {
+            let line = lines.get(self.y + i).map(String::as_str).unwrap_or_default();
+            res.push_str(&format!("{}│ {}{} │", goto(i + 1), line, " ".repeat(width.saturating_sub(_width(line)))));
+        }
+        res.push_str(&format!("{}└{}┘", goto(height + 1), "─".repeat(width + 2)));
+        res
+    }
+
+    /// Width and height inside the borders, as small as the value allows
+    fn _size(&self, max_x: u16, max_y: u16) -> (usize, usize) {
+        let available = max((max_x as usize).saturating_sub(6), 1);
+        let longest = self.lines.iter().map(|l| _width(l)).chain(std::iter::once(_width(&self.title) + 2)).max().unwrap_or(0);
+        let width = min(longest, available);
+        let height = min(_wrap(&self.lines, width).len(), max((max_y as usize).saturating_sub(4), 1));
+        (width, max(height, 1))
+    }
+
+    fn _wrapped(&self, max_x: u16) -> Vec<String> {
+        let (width, _) = self._size(max_x, u16::MAX);
+        _wrap(&self.lines, width)
+    }
+}
+
+/// Lines showing `value` in full : indented JSON and XML, hex dump of bytea, text as is
+pub fn format_value(type_: &Type, value: Option<&str>) -> Vec<String> {
+    let value = match value {
+        Some(v) => v,
+        None => return vec![String::from("NULL")],
+    };
+    let formatted = match *type_ {
+        Type::JSON | Type::JSONB => _indent_json(value),
+        Type::XML => _indent_xml(value),
+        Type::BYTEA => _hex_dump(value),
+        _ => None,
+    };
+    formatted.unwrap_or_else(|| value.lines().map(String::from).collect())
+}
+
+/// Keeps the order of keys and the text of values, only whitespace changes
+fn _indent_json(value: &str) -> Option<Vec<String>> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut depth: usize = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = value.chars().peekable();
+    let new_line = |lines: &mut Vec<String>, line: &mut String, depth: usize| {
+        lines.push(std::mem::take(line));
+        line.push_str(&INDENT.repeat(depth));
+    };
+    while let Some(c) = chars.next() {
+        if in_string {
+            line.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {},
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                line.push(c);
+            },
+            '{' | '[' => {
+                line.push(c);
+                while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                    chars.next();
+                }
+                // Empty objects and arrays stay on one line
+                if let Some(&close) = chars.peek().filter(|&&n| n == if c == '{' { '}' } else { ']' }) {
+                    chars.next();
+                    line.push(close);
+                } else {
+                    depth += 1;
+                    new_line(&mut lines, &mut line, depth);
+                }
+            },
+            '}' | ']' => {
+                depth = depth.checked_sub(1)?;
+                new_line(&mut lines, &mut line, depth);
+                line.push(c);
+            },
+            ',' => {
+                line.push(c);
+                new_line(&mut lines, &mut line, depth);
+            },
+            ':' => line.push_str(": "),
+            c if c.is_whitespace() => {},
+            c => line.push(c),
+        }
+    }
+    if in_string || depth > 0 {
+        return None;
+    }
+    lines.push(line);
+    Some(lines)
+}
+
+/// One tag per line, indented by depth, text between tags on its own line
+fn _indent_xml(value: &str) -> Option<Vec<String>> {
+    let mut lines = Vec::new();
+    let mut depth: usize = 0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let (token, tail) = if rest.starts_with('<') {
+            let end = rest.find('>')? + 1;
+            rest.split_at(end)
+        } else {
+            rest.split_at(rest.find('<').unwrap_or(rest.len()))
+        };
+        rest = tail;
+        let token = token.trim();
+        if token.is_empty() {
+            continue;
+        }
+        if token.starts_with("</") {
+            depth = depth.saturating_sub(1);
+        }
+        lines.push(format!("{}{}", INDENT.repeat(depth), token));
+        let opens = token.starts_with('<') && !token.starts_with("</") && !token.starts_with("<?")
+            && !token.starts_with("<!") && !token.ends_with("/>");
+        if opens {
+            depth += 1;
+        }
+    }
+    Some(lines)
+}
+
+/// Offset, bytes in hex and printable ASCII, from the `\x` hex format of the server
+fn _hex_dump(value: &str) -> Option<Vec<String>> {
+    let hex = value.strip_prefix("\\x")?;
+    let bytes = (0..hex.len()).step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect::<Option<Vec<u8>>>()?;
+    let mut lines: Vec<String> = bytes.chunks(HEX_WIDTH).enumerate().map(|(i, chunk)| {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+        format!("{:08x}  {:width$}  |{}|", i * HEX_WIDTH, hex.join(" "), ascii, width = HEX_WIDTH * 3 - 1)
+    }).collect();
+    lines.push(format!("{} bytes", bytes.len()));
+    Some(lines)
+}
+
+/// Cuts lines longer than `width` columns, tabs are shown as spaces
+fn _wrap(lines: &[String], width: usize) -> Vec<String> {
+    let mut res = Vec::new();
+    for line in lines {
+        let mut current = String::new();
+        let mut current_width = 0;
+        for c in line.chars() {
+            let c = if c == '\t' { ' ' } else { c };
+            let w = c.width().unwrap_or(0);
+            if current_width + w > width && !current.is_empty() {
+                res.push(std::mem::take(&mut current));
+                current_width = 0;
+            }
+            current.push(c);
+            current_width += w;
+        }
+        res.push(current);
+    }
+    res
+}
+
+fn _truncate(s: &str, width: usize) -> String {
+    _wrap(&[s.to_string()], width).swap_remove(0)
+}
+
+fn _width(s: &str) -> usize {
+    s.chars().map(|c| if c == '\t' { 1 } else { c.width().unwrap_or(0) }).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json() {
+        let lines = format_value(&Type::JSONB, Some(r#"{"b": [1, {}], "a": {"s": "x, {y}"}}"#));
+        assert_eq!(lines, vec!["{", "  \"b\": [", "    1,", "    {}", "  ],", "  \"a\": {", "    \"s\": \"x, {y}\"", "  }", "}"]);
+        assert_eq!(format_value(&Type::JSON, Some("[1")), vec!["[1"]);
+    }
+
+    #[test]
+    fn xml() {
+        let lines = format_value(&Type::XML, Some("<?xml version=\"1.0\"?><a><b x=\"1\"/><c>text</c></a>"));
+        assert_eq!(lines, vec!["<?xml version=\"1.0\"?>", "<a>", "  <b x=\"1\"/>", "  <c>", "    text", "  </c>", "</a>"]);
+    }
+
+    #[test]
+    fn bytea() {
+        let lines = format_value(&Type::BYTEA, Some("\\x41420a"));
+        assert_eq!(lines, vec![format!("00000000  41 42 0a{}  |AB.|", " ".repeat(39)), String::from("3 bytes")]);
+        assert_eq!(format_value(&Type::TEXT, None), vec!["NULL"]);
+    }
+
+    #[test]
+    fn wrap() {
+        assert_eq!(_wrap(&[String::from("abcdef"), String::new()], 4), vec!["abcd", "ef", ""]);
+        assert_eq!(_wrap(&[String::from("日本語")], 4), vec!["日本", "語"]);
+    }
+}
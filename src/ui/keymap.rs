@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use crate::ui::event::{KeyEvent, Key, Modifiers};
+
+/// Where a binding applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Context {
+    /// The input line, in emacs mode and in vi insert mode
+    Input,
+    Pager,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    AcceptLine,
+    Quit,
+    BackwardChar,
+    ForwardChar,
+    BackwardWord,
+    ForwardWord,
+    BeginningOfLine,
+    EndOfLine,
+    PreviousLine,
+    NextLine,
+    HistoryPrev,
+    HistoryNext,
+    BackwardDeleteChar,
+    DeleteChar,
+    KillWord,
+    BackwardKillWord,
+    KillLine,
+    BackwardKillLine,
+    Yank,
+    YankPop,
+    TransposeChars,
+    Undo,
+    Redo,
+    ClearScreen,
+    EditInEditor,
+    JumpToMatching,
+    PagerUp,
+    PagerDown,
+    PagerLeft,
+    PagerRight,
+    PagerPageUp,
+    PagerPageDown,
+    PagerHalfPageUp,
+    PagerHalfPageDown,
+    PagerTop,
+    PagerBottom,
+    PagerNextColumn,
+    PagerPreviousColumn,
+    PagerFreezeColumns,
+    PagerQuit,
+    PagerSearchForward,
+    PagerSearchBackward,
+    PagerNextMatch,
+    PagerPreviousMatch,
+    PagerCellUp,
+    PagerCellDown,
+    PagerCellLeft,
+    PagerCellRight,
+    PagerInspect,
+    PagerSort,
+    PagerSortReset,
+    PagerFilter,
+    PagerYankCell,
+    PagerYankRowCsv,
+    PagerYankRowJson,
+    PagerYankRowInsert,
+    PagerYankResultCsv,
+    PagerYankResultJson,
+    PagerYankResultInsert,
+    PagerExport,
+}
+
+/// Name, context and default keys of every action
+const ACTIONS: &[(Action, &str, Context, &[&str])] = &[
+    (Action::AcceptLine, "accept-line", Context::Input, &["Enter"]),
+    (Action::Quit, "quit", Context::Input, &["Ctrl-c", "Ctrl-d"]),
+    (Action::BackwardChar, "backward-char", Context::Input, &["Left", "Ctrl-b"]),
+    (Action::ForwardChar, "forward-char", Context::Input, &["Right", "Ctrl-f"]),
+    (Action::BackwardWord, "backward-word", Context::Input, &["PageUp", "Ctrl-Left", "Alt-b"]),
+    (Action::ForwardWord, "forward-word", Context::Input, &["PageDown", "Ctrl-Right", "Alt-f"]),
+    (Action::BeginningOfLine, "beginning-of-line", Context::Input, &["Home", "Ctrl-a"]),
+    (Action::EndOfLine, "end-of-line", Context::Input, &["End", "Ctrl-e"]),
+    (Action::PreviousLine, "previous-line", Context::Input, &["Up"]),
+    (Action::NextLine, "next-line", Context::Input, &["Down"]),
+    (Action::HistoryPrev, "history-prev", Context::Input, &["Ctrl-Up"]),
+    (Action::HistoryNext, "history-next", Context::Input, &["Ctrl-Down"]),
+    (Action::BackwardDeleteChar, "backward-delete-char", Context::Input, &["Backspace"]),
+    (Action::DeleteChar, "delete-char", Context::Input, &["Delete"]),
+    (Action::KillWord, "kill-word", Context::Input, &["Alt-d"]),
+    (Action::BackwardKillWord, "backward-kill-word", Context::Input, &["Ctrl-w", "Alt-Backspace"]),
+    (Action::KillLine, "kill-line", Context::Input, &["Ctrl-k"]),
+    (Action::BackwardKillLine, "backward-kill-line", Context::Input, &["Ctrl-u"]),
+    (Action::Yank, "yank", Context::Input, &["Ctrl-y"]),
+    (Action::YankPop, "yank-pop", Context::Input, &["Alt-y"]),
+    (Action::TransposeChars, "transpose-chars", Context::Input, &["Ctrl-t"]),
+    (Action::Undo, "undo", Context::Input, &["Ctrl-_"]),
+    (Action::Redo, "redo", Context::Input, &["Alt-_"]),
+    (Action::ClearScreen, "clear-screen", Context::Input, &["Ctrl-l"]),
+    (Action::EditInEditor, "edit-in-editor", Context::Input, &["Ctrl-x Ctrl-e"]),
+    (Action::JumpToMatching, "jump-to-matching", Context::Input, &["Ctrl-]"]),
+    (Action::PagerUp, "pager-up", Context::Pager, &["Up"]),
+    (Action::PagerDown, "pager-down", Context::Pager, &["Down"]),
+    (Action::PagerLeft, "pager-left", Context::Pager, &["Left"]),
+    (Action::PagerRight, "pager-right", Context::Pager, &["Right"]),
+    (Action::PagerPageUp, "pager-page-up", Context::Pager, &["PageUp"]),
+    (Action::PagerPageDown, "pager-page-down", Context::Pager, &["PageDown", "Space"]),
+    (Action::PagerHalfPageUp, "pager-half-page-up", Context::Pager, &["Ctrl-u"]),
+    (Action::PagerHalfPageDown, "pager-half-page-down", Context::Pager, &["Ctrl-d"]),
+    (Action::PagerTop, "pager-top", Context::Pager, &["g", "Home"]),
+    (Action::PagerBottom, "pager-bottom", Context::Pager, &["G", "End"]),
+    (Action::PagerNextColumn, "pager-next-column", Context::Pager, &["w"]),
+    (Action::PagerPreviousColumn, "pager-previous-column", Context::Pager, &["b"]),
+    (Action::PagerFreezeColumns, "pager-freeze-columns", Context::Pager, &["f"]),
+    (Action::PagerQuit, "pager-quit", Context::Pager, &["q", "Esc"]),
+    (Action::PagerSearchForward, "pager-search-forward", Context::Pager, &["/"]),
+    (Action::PagerSearchBackward, "pager-search-backward", Context::Pager, &["?"]),
+    (Action::PagerNextMatch, "pager-next-match", Context::Pager, &["n"]),
+    (Action::PagerPreviousMatch, "pager-previous-match", Context::Pager, &["N"]),
+    (Action::PagerCellUp, "pager-cell-up", Context::Pager, &["k"]),
+    (Action::PagerCellDown, "pager-cell-down", Context::Pager, &["j"]),
+    (Action::PagerCellLeft, "pager-cell-left", Context::Pager, &["h"]),
+    (Action::PagerCellRight, "pager-cell-right", Context::Pager, &["l"]),
+    (Action::PagerInspect, "pager-inspect", Context::Pager, &["Enter"]),
+    (Action::PagerSort, "pager-sort", Context::Pager, &["s"]),
+    (Action::PagerSortReset, "pager-sort-reset", Context::Pager, &["S"]),
+    (Action::PagerFilter, "pager-filter", Context::Pager, &["&"]),
+    (Action::PagerYankCell, "pager-yank-cell", Context::Pager, &["y y"]),
+    (Action::PagerYankRowCsv, "pager-yank-row-csv", Context::Pager, &["y c"]),
+    (Action::PagerYankRowJson, "pager-yank-row-json", Context::Pager, &["y j"]),
+    (Action::PagerYankRowInsert, "pager-yank-row-insert", Context::Pager, &["y i"]),
+    (Action::PagerYankResultCsv, "pager-yank-result-csv", Context::Pager, &["Y c"]),
+    (Action::PagerYankResultJson, "pager-yank-result-json", Context::Pager, &["Y j"]),
+    (Action::PagerYankResultInsert, "pager-yank-result-insert", Context::Pager, &["Y i"]),
+    (Action::PagerExport, "pager-export", Context::Pager, &["e"]),
+];
+
+/// Named keys, termion reports Ctrl-\ Ctrl-] Ctrl-^ and Ctrl-_ as Ctrl-4 to Ctrl-7
+const KEY_NAMES: &[(&str, KeyEvent)] = &[
+    ("Ctrl-\\", KeyEvent::Ctrl(Key::Char('4'))),
+    ("Ctrl-]", KeyEvent::Ctrl(Key::Char('5'))),
+    ("Ctrl-^", KeyEvent::Ctrl(Key::Char('6'))),
+    ("Ctrl-_", KeyEvent::Ctrl(Key::Char('7'))),
+];
+
+const SPECIAL_KEYS: &[(&str, Key)] = &[
+    ("Enter", Key::Char('\n')),
+    ("Tab", Key::Char('\t')),
+    ("Space", Key::Char(' ')),
+    ("Esc", Key::Esc),
+    ("Backspace", Key::Backspace),
+    ("BackTab", Key::BackTab),
+    ("Left", Key::Left),
+    ("Right", Key::Right),
+    ("Up", Key::Up),
+    ("Down", Key::Down),
+    ("Home", Key::Home),
+    ("End", Key::End),
+    ("PageUp", Key::PageUp),
+    ("PageDown", Key::PageDown),
+    ("Delete", Key::Delete),
+    ("Insert", Key::Insert),
+];
+
+pub enum Lookup {
+    Action(Action),
+    /// The keys are the start of a longer binding
+    Pending,
+    Unbound,
+}
+
+pub struct Keymap {
+    bindings: HashMap<Context, HashMap<Vec<KeyEvent>, Action>>,
+}
+
+impl Keymap {
+    pub fn new() -> Keymap {
+        let mut bindings: HashMap<Context, HashMap<Vec<KeyEvent>, Action>> = HashMap::new();
+        for (action, _, context, keys) in ACTIONS {
+            for k in keys.iter() {
+                bindings.entry(*context).or_default().insert(parse_keys(k).unwrap(), *action);
+            }
+        }
+        Keymap { bindings }
+    }
+
+    /// Default bindings changed by `user`, a map of contexts (`input`, `pager`) to maps of keys to action names.
+    /// A `null` action removes the binding. Returns the keymap and the bindings that were ignored and why
+    pub fn with_bindings(user: &HashMap<String, HashMap<String, Option<String>>>) -> (Keymap, Vec<String>) {
+        let mut keymap = Keymap::new();
+        let mut errors = Vec::new();
+        let mut contexts: Vec<_> = user.iter().collect();
+        contexts.sort_by_key(|(name, _)| name.as_str());
+
+        for (context_name, keys) in contexts {
+            let context = match context_name.as_str() {
+                "input" => Context::Input,
+                "pager" => Context::Pager,
+                _ => {
+                    errors.push(format!("unknown context {}", context_name));
+                    continue;
+                },
+            };
+            // Removals first, they can make room for new bindings
+            let mut keys: Vec<_> = keys.iter().collect();
+            keys.sort_by_key(|(k, a)| (a.is_some(), k.to_string()));
+            let mut seen = HashMap::new();
+            for (k, action_name) in keys {
+                let parsed = match parse_keys(k) {
+                    Some(parsed) => parsed,
+                    None => {
+                        errors.push(format!("unknown key {}", k));
+                        continue;
+                    },
+                };
+                if let Some(previous) = seen.insert(parsed.clone(), k) {
+                    errors.push(format!("{} is bound twice, also as {}", k, previous));
+                    continue;
+                }
+                let bindings = keymap.bindings.entry(context).or_default();
+                match action_name {
+                    None => {
+                        bindings.remove(&parsed);
+                    },
+                    Some(name) => match ACTIONS.iter().find(|(_, n, c, _)| n == name && *c == context) {
+                        None => errors.push(format!("unknown {} action {}", context_name, name)),
+                        Some((action, _, _, _)) => {
+                            // A key sequence cannot be both a binding and the start of a longer one
+                            let conflict = bindings.iter()
+                                .find(|(other, _)| **other != parsed && (other.starts_with(&parsed) || parsed.starts_with(other)));
+                            match conflict {
+                                Some((other, a)) => errors.push(format!("{} conflicts with {} ({})", k, key_names(other), action_name_of(*a))),
+                                None => {
+                                    bindings.insert(parsed, *action);
+                                },
+                            }
+                        },
+                    },
+                }
+            }
+        }
+        (keymap, errors)
+    }
+
+    pub fn lookup(&self, context: Context, keys: &[KeyEvent]) -> Lookup {
+        let bindings = match self.bindings.get(&context) {
+            Some(b) => b,
+            None => return Lookup::Unbound,
+        };
+        if let Some(action) = bindings.get(keys) {
+            Lookup::Action(*action)
+        } else if bindings.keys().any(|k| k.starts_with(keys)) {
+            Lookup::Pending
+        } else {
+            Lookup::Unbound
+        }
+    }
+
+    /// One line per action, with its keys
+    pub fn lines(&self) -> Vec<String> {
+        ACTIONS.iter().map(|(action, name, context, _)| {
+            let mut keys: Vec<String> = self.bindings.get(context)
+                .map(|b| b.iter().filter(|(_, a)| *a == action).map(|(k, _)| key_names(k)).collect())
+                .unwrap_or_default();
+            keys.sort();
+            let context_name = match context {
+                Context::Input => "input",
+                Context::Pager => "pager",
+            };
+            format!("{:6} {:24} {}", context_name, name, keys.join(", "))
+        }).collect()
+    }
+}
+
+fn action_name_of(action: Action) -> &'static str {
+    ACTIONS.iter().find(|(a, _, _, _)| *a == action).map(|(_, n, _, _)| *n).unwrap_or_default()
+}
+
+/// Space separated keys, like `Ctrl-x Ctrl-e`
+pub fn parse_keys(s: &str) -> Option<Vec<KeyEvent>> {
+    let keys = s.split_whitespace().map(parse_key).collect::<Option<Vec<KeyEvent>>>()?;
+    if keys.is_empty() {
+        None
+    } else {
+        Some(keys)
+    }
+}
+
+/// `a`, `Enter`, `Ctrl-a`, `Alt-Left`, `F5`...
+pub fn parse_key(s: &str) -> Option<KeyEvent> {
+    if let Some((_, k)) = KEY_NAMES.iter().find(|(name, _)| *name == s) {
+        return Some(*k);
+    }
+    let mut modifiers = Modifiers::default();
+    let mut name = s;
+    while let Some((m, n)) = name.split_once('-').filter(|(_, n)| !n.is_empty()) {
+        match m {
+            "Ctrl" => modifiers.ctrl = true,
+            "Alt" => modifiers.alt = true,
+            "Shift" => modifiers.shift = true,
+            _ => break,
+        }
+        name = n;
+    }
+    let key = if let Some((_, k)) = SPECIAL_KEYS.iter().find(|(n, _)| *n == name) {
+        *k
+    } else if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()) {
+        Key::F(n)
+    } else {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.ctrl => Key::Char(c.to_ascii_lowercase()),
+            (Some(c), None) => Key::Char(c),
+            _ => return None,
+        }
+    };
+    Some(KeyEvent::new(key, modifiers))
+}
+
+pub fn key_names(keys: &[KeyEvent]) -> String {
+    keys.iter().map(key_name).collect::<Vec<String>>().join(" ")
+}
+
+pub fn key_name(key: &KeyEvent) -> String {
+    if let Some((name, _)) = KEY_NAMES.iter().find(|(_, k)| k == key) {
+        return name.to_string();
+    }
+    let (modifiers, key) = key.split();
+    let name = if let Some((n, _)) = SPECIAL_KEYS.iter().find(|(_, s)| *s == key) {
+        n.to_string()
+    } else {
+        match key {
+            Key::Char(c) => c.to_string(),
+            Key::F(n) => format!("F{}", n),
+            k => format!("{:?}", k),
+        }
+    };
+    let mut res = String::new();
+    for (set, m) in [(modifiers.ctrl, "Ctrl-"), (modifiers.alt, "Alt-"), (modifiers.shift, "Shift-")] {
+        if set {
+            res.push_str(m);
+        }
+    }
+    res + &name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _user(bindings: &[(&str, Option<&str>)]) -> HashMap<String, HashMap<String, Option<String>>> {
+        let mut input = HashMap::new();
+        for (k, a) in bindings {
+            input.insert(k.to_string(), a.map(String::from));
+        }
+        let mut user = HashMap::new();
+        user.insert(String::from("input"), input);
+        user
+    }
+
+    #[test]
+    fn key_notation() {
+        for name in &["a", "Enter", "Ctrl-a", "Alt-b", "Ctrl-Left", "PageUp", "F5", "Ctrl-_", "Alt-Backspace", "Shift-Left", "Ctrl-Alt-Right", "Ctrl-Shift-F5", "Alt--"] {
+            assert_eq!(key_name(&parse_key(name).unwrap()), *name);
+        }
+        assert!(parse_key("Hyper-a").is_none());
+        assert_eq!(parse_keys("Ctrl-x Ctrl-e").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn sequences() {
+        let keymap = Keymap::new();
+        let ctrl_x = parse_keys("Ctrl-x").unwrap();
+
+        assert!(matches!(keymap.lookup(Context::Input, &ctrl_x), Lookup::Pending));
+        assert!(matches!(keymap.lookup(Context::Input, &parse_keys("Ctrl-x Ctrl-e").unwrap()), Lookup::Action(Action::EditInEditor)));
+        assert!(matches!(keymap.lookup(Context::Input, &parse_keys("Ctrl-x a").unwrap()), Lookup::Unbound));
+    }
+
+    #[test]
+    fn user_bindings() {
+        let (keymap, errors) = Keymap::with_bindings(&_user(&[("Ctrl-p", Some("history-prev")), ("PageUp", None)]));
+
+        assert!(errors.is_empty());
+        assert!(matches!(keymap.lookup(Context::Input, &parse_keys("Ctrl-p").unwrap()), Lookup::Action(Action::HistoryPrev)));
+        assert!(matches!(keymap.lookup(Context::Input, &parse_keys("PageUp").unwrap()), Lookup::Unbound));
+    }
+
+    #[test]
+    fn conflicts() {
+        let (keymap, errors) = Keymap::with_bindings(&_user(&[
+            ("Ctrl-x", Some("undo")),
+            ("Alt-z", Some("pager-up")),
+            ("Ctrl-q", Some("no-such-action")),
+        ]));
+
+        assert_eq!(errors.len(), 3);
+        assert!(errors.iter().any(|e| e.contains("conflicts with Ctrl-x Ctrl-e")));
+        assert!(matches!(keymap.lookup(Context::Input, &parse_keys("Ctrl-x").unwrap()), Lookup::Pending));
+    }
+}
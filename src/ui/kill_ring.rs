@@ -0,0 +1,88 @@
+const KILL_RING_MAX: usize = 32;
+
+pub enum KillDirection {
+    Forward,
+    Backward,
+}
+
+/// Emacs style kill ring : killed text can be yanked back, and older kills are reachable with yank-pop
+pub struct KillRing {
+    ring: Vec<Vec<char>>,
+    index: usize,
+}
+
+impl KillRing {
+    pub fn new() -> KillRing {
+        KillRing {
+            ring: Vec::new(),
+            index: 0,
+        }
+    }
+
+    /// When `merge` is set (consecutive kills), the text is added to the last kill instead of creating a new one
+    pub fn kill(&mut self, mut text: Vec<char>, direction: KillDirection, merge: bool) {
+        match self.ring.last_mut() {
+            Some(last) if merge => {
+                match direction {
+                    KillDirection::Forward => last.append(&mut text),
+                    KillDirection::Backward => {
+                        text.append(last);
+                        *last = text;
+                    }
+                }
+            },
+            _ => {
+                if self.ring.len() == KILL_RING_MAX {
+                    self.ring.remove(0);
+                }
+                self.ring.push(text);
+            }
+        }
+        self.index = self.ring.len() - 1;
+    }
+
+    pub fn yank(&mut self) -> Option<&[char]> {
+        self.index = self.ring.len().checked_sub(1)?;
+        Some(&self.ring[self.index][..])
+    }
+
+    /// Moves to the previous kill, wrapping around to the most recent one
+    pub fn rotate(&mut self) -> Option<&[char]> {
+        if self.ring.is_empty() {
+            return None;
+        }
+        self.index = if self.index == 0 { self.ring.len() - 1 } else { self.index - 1 };
+        Some(&self.ring[self.index][..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn merge_consecutive_kills() {
+        let mut kr = KillRing::new();
+        kr.kill(_chars("world"), KillDirection::Backward, false);
+        kr.kill(_chars("hello "), KillDirection::Backward, true);
+        kr.kill(_chars("!"), KillDirection::Forward, true);
+
+        assert_eq!(kr.yank(), Some(&_chars("hello world!")[..]));
+    }
+
+    #[test]
+    fn rotate() {
+        let mut kr = KillRing::new();
+        assert_eq!(kr.yank(), None);
+        kr.kill(_chars("one"), KillDirection::Forward, false);
+        kr.kill(_chars("two"), KillDirection::Forward, false);
+
+        assert_eq!(kr.yank(), Some(&_chars("two")[..]));
+        assert_eq!(kr.rotate(), Some(&_chars("one")[..]));
+        assert_eq!(kr.rotate(), Some(&_chars("two")[..]));
+    }
+}
@@ -0,0 +1,190 @@
+use std::cmp::max;
+use unicode_width::UnicodeWidthChar;
+
+const TAB_WIDTH: usize = 8;
+
+/// A line as displayed on the terminal, `[start, end)` in the buffer
+struct VisualLine {
+    start: usize,
+    end: usize,
+    /// Number of the buffer line whose prompt is displayed before the line, wrapped lines have none
+    prompt: Option<usize>,
+}
+
+/// Where every character of the buffer is displayed, for a terminal width and the prompt widths
+pub struct Layout {
+    /// `(column, line)` from 0 of every character, and of the position past the end
+    positions: Vec<(usize, usize)>,
+    lines: Vec<VisualLine>,
+}
+
+impl Layout {
+    /// `prompt_widths` has the width of the prompt of every line of the buffer, the first one and after every newline
+    pub fn new(buffer: &[char], width: usize, prompt_widths: &[usize]) -> Layout {
+        let width = max(width, 1);
+        let prompt_width = |n: usize| prompt_widths.get(n).copied().unwrap_or(0);
+        let mut positions = Vec::with_capacity(buffer.len() + 1);
+        let mut lines = vec![VisualLine { start: 0, end: 0, prompt: Some(0) }];
+        let mut buffer_line = 0;
+        let mut col = prompt_width(0);
+
+        for (i, &c) in buffer.iter().enumerate() {
+            if c == '\n' {
+                if col >= width {
+                    lines.last_mut().unwrap().end = i;
+                    lines.push(VisualLine { start: i, end: i, prompt: None });
+                    col = 0;
+                }
+                positions.push((col, lines.len() - 1));
+                lines.last_mut().unwrap().end = i;
+                buffer_line += 1;
+                lines.push(VisualLine { start: i + 1, end: i + 1, prompt: Some(buffer_line) });
+                col = prompt_width(buffer_line);
+                continue;
+            }
+            let mut w = _width(c, col);
+            if col + max(w, 1) > width {
+                lines.last_mut().unwrap().end = i;
+                lines.push(VisualLine { start: i, end: i, prompt: None });
+                col = 0;
+                w = _width(c, col);
+            }
+            positions.push((col, lines.len() - 1));
+            // A tab never wraps, it stops at the end of the line
+            col += if c == '\t' { std::cmp::min(w, width - col) } else { w };
+        }
+
+        lines.last_mut().unwrap().end = buffer.len();
+        if col >= width {
+            // The cursor after a full line is at the start of the next one
+            lines.push(VisualLine { start: buffer.len(), end: buffer.len(), prompt: None });
+            col = 0;
+        }
+        positions.push((col, lines.len() - 1));
+        Layout { positions, lines }
+    }
+
+    /// `(column, line)` of the character at `index`
+    pub fn position(&self, index: usize) -> (usize, usize) {
+        self.positions[index]
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Index of the character displayed at `column` on `line`, or of the last one before it
+    pub fn index_at(&self, line: usize, column: usize) -> usize {
+        let l = &self.lines[line];
+        let mut index = l.start;
+        for i in l.start..=l.end {
+            if self.positions[i].1 != line || self.positions[i].0 > column {
+                break;
+            }
+            index = i;
+        }
+        index
+    }
+
+    /// Text of the lines `top..top + height`, separated by `\r\n` instead of relying on the terminal wrapping.
+    /// `style` gives the escape sequence to display a character with, if any
+    pub fn render<F: Fn(usize) -> Option<String>>(&self, buffer: &[char], prompts: &[String],
+                                                  top: usize, height: usize, style: F) -> String {
+        let mut s = String::new();
+        for (n, l) in self.lines.iter().enumerate().skip(top).take(height) {
+            if n > top {
+                s.push_str("\r\n");
+            }
+            if let Some(prompt) = l.prompt.and_then(|n| prompts.get(n)) {
+                s.push_str(prompt);
+            }
+            for (i, &c) in buffer.iter().enumerate().take(l.end).skip(l.start) {
+                let st = style(i);
+                if let Some(st) = &st {
+                    s.push_str(st);
+                }
+                if c == '\t' {
+                    let next = if i + 1 < self.positions.len() && self.positions[i + 1].1 == n { self.positions[i + 1].0 } else { self.positions[i].0 + 1 };
+                    s.push_str(&" ".repeat(max(next, self.positions[i].0 + 1) - self.positions[i].0));
+                } else {
+                    s.push(c);
+                }
+                if st.is_some() {
+                    s.push_str(termion::style::Reset.as_ref());
+                }
+            }
+        }
+        s
+    }
+}
+
+fn _width(c: char, col: usize) -> usize {
+    if c == '\t' {
+        TAB_WIDTH - col % TAB_WIDTH
+    } else {
+        c.width().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn wrapping() {
+        let buffer = _chars("abcdefgh");
+        let layout = Layout::new(&buffer, 5, &[3]);
+
+        assert_eq!(layout.position(0), (3, 0));
+        assert_eq!(layout.position(2), (0, 1));
+        assert_eq!(layout.position(7), (0, 2));
+        assert_eq!(layout.position(8), (1, 2));
+        assert_eq!(layout.line_count(), 3);
+    }
+
+    #[test]
+    fn cursor_after_full_line() {
+        let buffer = _chars("ab");
+        let layout = Layout::new(&buffer, 5, &[3]);
+
+        assert_eq!(layout.position(2), (0, 1));
+        assert_eq!(layout.line_count(), 2);
+    }
+
+    #[test]
+    fn newlines_and_continuation_prompt() {
+        let buffer = _chars("select\n1");
+        let layout = Layout::new(&buffer, 80, &[3, 2]);
+        let prompts = vec![String::from("$> "), String::from("> ")];
+
+        assert_eq!(layout.position(6), (9, 0));
+        assert_eq!(layout.position(7), (2, 1));
+        assert_eq!(layout.render(&buffer, &prompts, 0, 2, |_| None), "$> select\r\n> 1");
+    }
+
+    #[test]
+    fn wide_characters_and_tabs() {
+        let buffer = _chars("a\tb日本");
+        let layout = Layout::new(&buffer, 12, &[]);
+
+        assert_eq!(layout.position(2), (8, 0));
+        assert_eq!(layout.position(3), (9, 0));
+        // Does not fit in the last column
+        assert_eq!(layout.position(4), (0, 1));
+        assert_eq!(layout.render(&buffer, &[], 0, 2, |_| None), "a       b日\r\n本");
+    }
+
+    #[test]
+    fn index_at() {
+        let buffer = _chars("select\n1\nfrom t");
+        let layout = Layout::new(&buffer, 80, &[3]);
+
+        assert_eq!(layout.index_at(1, 4), 8);
+        assert_eq!(layout.index_at(2, 2), 11);
+        assert_eq!(layout.index_at(0, 0), 0);
+    }
+}
@@ -2,3 +2,12 @@ pub mod event_loop;
 pub mod text_input;
 pub mod text_view;
 pub mod event;
+pub mod input;
+pub mod kill_ring;
+pub mod vi;
+pub mod undo;
+pub mod layout;
+pub mod keymap;
+pub mod inspector;
+pub mod rows;
+pub mod clipboard;
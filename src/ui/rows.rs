@@ -0,0 +1,210 @@
+use postgres::types::Type;
+use std::cmp::Ordering;
+use crate::regex::Regex;
+use crate::sql::{self, QueryResult};
+
+/// A column to sort the rows on, the first key of a list wins
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SortKey {
+    pub column: usize,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Condition on the values of a column
+#[derive(Debug)]
+pub enum Filter {
+    Contains(Regex),
+    /// Negated
+    Matches(Regex, bool),
+    Compare(Operator, String),
+    /// Negated
+    Null(bool),
+}
+
+impl Filter {
+    /// `null`, `!null`, `~regex`, `!~regex`, `=`, `!=`, `<>`, `<`, `<=`, `>`, `>=` followed by a value,
+    /// anything else is a substring to look for
+    pub fn parse(text: &str) -> Result<Filter, String> {
+        let text = text.trim();
+        match text {
+            "null" => return Ok(Filter::Null(false)),
+            "!null" => return Ok(Filter::Null(true)),
+            _ => {},
+        }
+        if let Some(pattern) = text.strip_prefix("!~") {
+            return Regex::new(pattern.trim_start()).map(|r| Filter::Matches(r, true));
+        }
+        if let Some(pattern) = text.strip_prefix('~') {
+            return Regex::new(pattern.trim_start()).map(|r| Filter::Matches(r, false));
+        }
+        // Longest operators first
+        let operators = [("<=", Operator::Le), (">=", Operator::Ge), ("!=", Operator::Ne), ("<>", Operator::Ne),
+                         ("=", Operator::Eq), ("<", Operator::Lt), (">", Operator::Gt)];
+        for (prefix, operator) in &operators {
+            if let Some(value) = text.strip_prefix(prefix) {
+                return Ok(Filter::Compare(*operator, value.trim().to_string()));
+            }
+        }
+        Ok(Filter::Contains(Regex::literal(text)))
+    }
+
+    pub fn accepts(&self, type_: &Type, value: Option<&str>) -> bool {
+        match (self, value) {
+            (Filter::Null(negated), v) => v.is_none() != *negated,
+            (_, None) => false,
+            (Filter::Contains(regex), Some(v)) => regex.is_match(v),
+            (Filter::Matches(regex, negated), Some(v)) => regex.is_match(v) != *negated,
+            (Filter::Compare(operator, other), Some(v)) => {
+                let ordering = compare(type_, v, other);
+                match operator {
+                    Operator::Eq => ordering == Ordering::Equal,
+                    Operator::Ne => ordering != Ordering::Equal,
+                    Operator::Lt => ordering == Ordering::Less,
+                    Operator::Le => ordering != Ordering::Greater,
+                    Operator::Gt => ordering == Ordering::Greater,
+                    Operator::Ge => ordering != Ordering::Less,
+                }
+            },
+        }
+    }
+}
+
+/// Numbers compare by value, integers and NUMERIC exactly, other values as text,
+/// which suits the ISO dates and times sent by the server
+pub fn compare(type_: &Type, a: &str, b: &str) -> Ordering {
+    let integer = matches!(*type_, Type::INT2 | Type::INT4 | Type::INT8 | Type::OID);
+    if integer {
+        if let (Ok(a), Ok(b)) = (a.trim().parse::<i128>(), b.trim().parse::<i128>()) {
+            return a.cmp(&b);
+        }
+    }
+    // The value of a filter on an integer column can have a fraction
+    if integer || *type_ == Type::NUMERIC {
+        if let (Some(a), Some(b)) = (_decimal(a), _decimal(b)) {
+            return _compare_decimals(a, b);
+        }
+    }
+    if sql::is_numeric(type_) {
+        if let (Ok(a), Ok(b)) = (a.parse::<f64>(), b.parse::<f64>()) {
+            return a.total_cmp(&b);
+        }
+    }
+    a.cmp(b)
+}
+
+/// A NUMERIC as text : -1 for -Infinity, 0 for a number, 1 for Infinity and 2 for NaN, then for a number
+/// whether it is negative and its digits, without leading zeros before the point and trailing ones after it
+type Decimal<'a> = (i8, bool, &'a str, &'a str);
+
+fn _decimal(s: &str) -> Option<Decimal<'_>> {
+    let s = s.trim();
+    match s.to_lowercase().as_str() {
+        "nan" => return Some((2, false, "", "")),
+        "infinity" | "+infinity" => return Some((1, false, "", "")),
+        "-infinity" => return Some((-1, false, "", "")),
+        _ => {},
+    }
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if integer.is_empty() && fraction.is_empty() || !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (integer, fraction) = (integer.trim_start_matches('0'), fraction.trim_end_matches('0'));
+    // Zero has no sign
+    Some((0, negative && !(integer.is_empty() && fraction.is_empty()), integer, fraction))
+}
+
+/// By sign, then length of the integer part, then digits. NaN is greater than anything else, like in PostgreSQL
+fn _compare_decimals(a: Decimal, b: Decimal) -> Ordering {
+    let ((kind_a, negative_a, integer_a, fraction_a), (kind_b, negative_b, integer_b, fraction_b)) = (a, b);
+    if kind_a != 0 || kind_b != 0 || negative_a != negative_b {
+        return (kind_a, !negative_a).cmp(&(kind_b, !negative_b));
+    }
+    let magnitude = integer_a.len().cmp(&integer_b.len())
+        .then(integer_a.cmp(integer_b))
+        .then(fraction_a.cmp(fraction_b));
+    if negative_a { magnitude.reverse() } else { magnitude }
+}
+
+/// Indexes of the rows of `result` accepted by every filter, in the order of the sort keys.
+/// The sort is stable and NULLs come last, like in PostgreSQL, or first when descending
+pub fn arrange(result: &QueryResult, sort: &[SortKey], filters: &[(usize, Filter)]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..result.rows.len()).filter(|&i| {
+        filters.iter().all(|(column, filter)| filter.accepts(&result.columns[*column].type_, result.rows[i][*column].as_deref()))
+    }).collect();
+    order.sort_by(|&i, &j| {
+        sort.iter().map(|key| {
+            let type_ = &result.columns[key.column].type_;
+            let ordering = match (&result.rows[i][key.column], &result.rows[j][key.column]) {
+                (Some(a), Some(b)) => compare(type_, a, b),
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+            };
+            if key.descending { ordering.reverse() } else { ordering }
+        }).find(|o| *o != Ordering::Equal).unwrap_or(Ordering::Equal)
+    });
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::ResultColumn;
+
+    fn _result() -> QueryResult {
+        let column = |name: &str, type_: Type| ResultColumn { name: name.to_string(), max_size: 0, type_ };
+        let rows = [("9", "b"), ("10", "a"), ("9", "a"), ("", "c")].iter().map(|(n, s)| {
+            vec![if n.is_empty() { None } else { Some(n.to_string()) }, Some(s.to_string())]
+        }).collect();
+        QueryResult { columns: vec![column("n", Type::INT4), column("s", Type::TEXT)], rows }
+    }
+
+    #[test]
+    fn sort() {
+        let result = _result();
+        let key = |column, descending| SortKey { column, descending };
+        assert_eq!(arrange(&result, &[key(0, false)], &[]), vec![0, 2, 1, 3]);
+        assert_eq!(arrange(&result, &[key(0, true)], &[]), vec![3, 1, 0, 2]);
+        assert_eq!(arrange(&result, &[key(1, false), key(0, true)], &[]), vec![1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn filter() {
+        let result = _result();
+        let filter = |column, text| vec![(column, Filter::parse(text).unwrap())];
+        assert_eq!(arrange(&result, &[], &filter(0, ">= 9")), vec![0, 1, 2]);
+        assert_eq!(arrange(&result, &[], &filter(0, "<10")), vec![0, 2]);
+        assert_eq!(arrange(&result, &[], &filter(0, "null")), vec![3]);
+        assert!(arrange(&result, &[], &filter(1, "A")).is_empty());
+        assert_eq!(arrange(&result, &[], &filter(1, "!~[ab]")), vec![3]);
+        assert!(Filter::parse("~(").is_err());
+    }
+
+    #[test]
+    fn exact_numbers() {
+        assert_eq!(compare(&Type::INT8, "9007199254740993", "9007199254740992"), Ordering::Greater);
+        assert_eq!(compare(&Type::INT4, "9", "9.5"), Ordering::Less);
+        assert_eq!(compare(&Type::NUMERIC, "12345678901234567890.1", "12345678901234567890.09"), Ordering::Greater);
+        assert_eq!(compare(&Type::NUMERIC, "0.10", "000.1"), Ordering::Equal);
+        assert_eq!(compare(&Type::NUMERIC, "-0.0", "0"), Ordering::Equal);
+        assert_eq!(compare(&Type::NUMERIC, "-2.5", "-2.4"), Ordering::Less);
+        assert_eq!(compare(&Type::NUMERIC, "-10", "9"), Ordering::Less);
+        assert_eq!(compare(&Type::NUMERIC, "-Infinity", "-1e100"), Ordering::Less);
+        assert_eq!(compare(&Type::NUMERIC, "NaN", "Infinity"), Ordering::Greater);
+        assert_eq!(compare(&Type::NUMERIC, "NaN", "NaN"), Ordering::Equal);
+    }
+}
@@ -1,94 +1,143 @@
 use termion::cursor::DetectCursorPos;
-use crate::ui::event::{TrueEvent, KeyEvent, Key};
+use unicode_width::UnicodeWidthStr;
+use crate::history::History;
+use crate::lexer;
+use crate::prompt::Prompt;
+use termion::event::MouseButton;
+use crate::ui::event::{TrueEvent, KeyEvent, Key, MouseEvent};
+use crate::ui::keymap::{Keymap, Context, Lookup, Action};
+use crate::ui::kill_ring::{KillRing, KillDirection};
+use crate::ui::layout::Layout;
+use crate::ui::undo::UndoStack;
+use crate::ui::vi::{self, ViState, ViMode, ViCommand, Operator, InsertPosition};
+use crate::settings::EditMode;
+use std::cmp::min;
 use std::io::{Write};
 
-const PROMPT: &str = "$> ";
-
 pub struct TermPos {
-    x: u16,
+    /// Screen line where the first displayed line of the input is
     y: u16,
+    /// First displayed visual line, when the input is higher than the terminal
+    top: usize,
     buffer: Vec<char>,
     current_index: usize,
-    d_y: u16,
     max_x: u16,
     max_y: u16,
-    prompt_len: usize,
+    /// Rest of the suggested history entry, displayed dimmed after the buffer
+    suggestion: Vec<char>,
+    prompt: Prompt,
+    /// Displayed before the prompt, the vi mode
+    indicator: &'static str,
 }
 
 impl TermPos {
-    pub fn new(stdout: &mut termion::raw::RawTerminal<std::io::Stdout>) -> TermPos {
+    pub fn new(stdout: &mut termion::raw::RawTerminal<std::io::Stdout>, prompt: Prompt, indicator: &'static str) -> TermPos {
         let (max_x, max_y) = termion::terminal_size().unwrap();
         let pos = stdout.cursor_pos().unwrap();
         TermPos {
-            x: PROMPT.len() as u16 + 1,
             y: pos.1,
+            top: 0,
             buffer: Vec::new(),
             current_index: 0,
-            d_y: 0,
             max_x,
             max_y,
-            prompt_len: PROMPT.len()
+            suggestion: Vec::new(),
+            prompt,
+            indicator,
+        }
+    }
+
+    /// Text and width of the prompt of every line of `text`
+    fn prompts(&self, text: &[char]) -> Vec<(String, usize)> {
+        let (first, width) = self.prompt.first();
+        let mut prompts = vec![(format!("{}{}", self.indicator, first), width + self.indicator.width())];
+        let states = lexer::scan(text);
+        for (i, &c) in text.iter().enumerate() {
+            if c == '\n' {
+                prompts.push(self.prompt.continuation(&states[i + 1]));
+            }
         }
+        prompts
     }
 
-    fn _compute_max_dy(&self) -> u16 {
-        ((self.prompt_len + self.buffer.len()) / self.max_x as usize) as u16
+    fn layout(&self, text: &[char]) -> Layout {
+        let widths: Vec<usize> = self.prompts(text).iter().map(|p| p.1).collect();
+        Layout::new(text, self.max_x as usize, &widths)
+    }
+
+    /// The buffer followed by the suggestion
+    fn displayed(&self) -> Vec<char> {
+        let mut text = self.buffer.clone();
+        text.extend(&self.suggestion);
+        text
     }
 
     fn char(&mut self, c: char) {
         self.buffer.insert(self.current_index, c);
-        self.current_index += 1;
-        self.x += 1;
-        if self.x > self.max_x {
-            self.x = 1;
-            self.d_y += 1;
+        self.move_to(self.current_index + 1);
+    }
+
+    /// Takes the new terminal size into account, the buffer is wrapped again on the next display
+    fn resize(&mut self) {
+        let (max_x, max_y) = termion::terminal_size().unwrap_or((self.max_x, self.max_y));
+        self.max_x = max_x;
+        self.max_y = max_y;
+        self.y = min(self.y, max_y);
+    }
+
+    /// Scrolls the terminal until the displayed lines fit below the input start, and scrolls
+    /// the input itself when it is higher than the terminal so the cursor line stays visible.
+    /// Returns the layout, the number of displayed lines and the cursor screen position
+    fn scroll(&mut self, text: &[char]) -> (Layout, usize, (u16, u16)) {
+        let layout = self.layout(text);
+        let shown = min(layout.line_count(), self.max_y as usize);
+        let overflow = (self.y as usize + shown - 1).saturating_sub(self.max_y as usize);
+        if overflow > 0 {
+            print!("{}{}", termion::cursor::Goto(1, self.max_y), "\n".repeat(overflow));
+            self.y -= overflow as u16;
         }
-        if self.y + self._compute_max_dy() > self.max_y {
-            self.y -= 1;
-            print!("{}\n{}", termion::cursor::Goto(1, self.max_y), termion::clear::CurrentLine);
+
+        let (col, line) = layout.position(self.current_index);
+        self.top = min(self.top, layout.line_count() - shown);
+        if line < self.top {
+            self.top = line;
+        } else if line >= self.top + shown {
+            self.top = line + 1 - shown;
         }
+        let cursor = (col as u16 + 1, self.y + (line - self.top) as u16);
+        (layout, shown, cursor)
     }
 
     fn left(&mut self) {
         if self.current_index > 0 {
-            self.current_index -= 1;
-            if self.x == 1 {
-                self.x = self.max_x;
-                self.d_y -= 1;
-            }
-            else {
-                self.x -= 1;
-            }
+            self.move_to(self.current_index - 1);
         }
     }
 
     fn right(&mut self) {
         if self.current_index < self.buffer.len() {
-            self.current_index += 1;
-            if self.x == self.max_x {
-                self.x = 1;
-                self.d_y += 1;
-            }
-            else {
-                self.x += 1;
-            }
+            self.move_to(self.current_index + 1);
         }
     }
 
+    /// Moves to the same column on the previous visual line, or to the start on the first one
     fn up(&mut self) {
-        if self.d_y > 0 {
-            self.current_index -= self.max_x as usize;
-            self.d_y -= 1;
+        let layout = self.layout(&self.buffer);
+        let (col, line) = layout.position(self.current_index);
+        if line > 0 {
+            self.move_to(layout.index_at(line - 1, col));
         }
         else {
             self.beg();
         }
     }
 
+    /// Moves to the same column on the next visual line, or to the end on the last one
     fn down(&mut self) {
-        if self.current_index + (self.max_x as usize) < self.buffer.len() {
-            self.current_index += self.max_x as usize;
-            self.d_y += 1;
+        let layout = self.layout(&self.buffer);
+        let (col, line) = layout.position(self.current_index);
+        if line + 1 < layout.line_count() {
+            self.move_to(layout.index_at(line + 1, col));
         }
         else {
             self.end();
@@ -109,15 +158,11 @@ impl TermPos {
     }
 
     fn beg(&mut self) {
-        self.current_index = 0;
-        self.x = self.prompt_len as u16 + 1;
-        self.d_y = 0;
+        self.move_to(0);
     }
 
     fn end(&mut self) {
-        self.current_index = self.buffer.len();
-        self.x = ((self.prompt_len + self.buffer.len()) % self.max_x as usize) as u16 + 1;
-        self.d_y = self._compute_max_dy();
+        self.move_to(self.buffer.len());
     }
 
     fn word_left(&mut self) {
@@ -156,6 +201,67 @@ impl TermPos {
         }
     }
 
+    fn move_to(&mut self, index: usize) {
+        self.current_index = index;
+    }
+
+    /// Moves to the character displayed at a terminal position, if it is on the input
+    fn click(&mut self, x: u16, y: u16) {
+        let layout = self.layout(&self.buffer);
+        if y < self.y {
+            return;
+        }
+        let line = self.top + (y - self.y) as usize;
+        if line < layout.line_count() {
+            self.move_to(layout.index_at(line, (x as usize).saturating_sub(1)));
+        }
+    }
+
+    fn insert(&mut self, text: &[char]) {
+        for &c in text {
+            self.char(c);
+        }
+    }
+
+    fn remove(&mut self, start: usize, end: usize) -> Vec<char> {
+        let removed = self.buffer.drain(start..end).collect();
+        self.move_to(start);
+        removed
+    }
+
+    fn word_start_before(&self) -> usize {
+        let mut i = self.current_index;
+        while i > 0 && self.buffer[i - 1].is_whitespace() {
+            i -= 1;
+        }
+        while i > 0 && !self.buffer[i - 1].is_whitespace() {
+            i -= 1;
+        }
+        i
+    }
+
+    fn word_end_after(&self) -> usize {
+        let mut i = self.current_index;
+        while i < self.buffer.len() && self.buffer[i].is_whitespace() {
+            i += 1;
+        }
+        while i < self.buffer.len() && !self.buffer[i].is_whitespace() {
+            i += 1;
+        }
+        i
+    }
+
+    fn transpose(&mut self) {
+        if self.current_index == 0 || self.buffer.len() < 2 {
+            return;
+        }
+        if self.current_index == self.buffer.len() {
+            self.left();
+        }
+        self.buffer.swap(self.current_index - 1, self.current_index);
+        self.right();
+    }
+
     fn clear_term(&mut self) {
         self.y = 1;
         print!("{}", termion::clear::All);
@@ -167,93 +273,471 @@ pub enum TextInputEvent {
     Quit,
     HistoryPrev,
     HistoryNext,
+    /// Ctrl-X Ctrl-E : edit the buffer in an external editor
+    Edit,
     Buffer(Vec<char>, String),
     None,
 }
 
-pub struct TextInput {
+#[derive(PartialEq, Clone, Copy)]
+enum LastAction {
+    Typing,
+    Deleting,
+    Kill,
+    /// Start and length of the yanked text, replaced by yank-pop
+    Yank(usize, usize),
+    HistoryRecall,
+    Other,
+}
+
+pub struct TextInput<'a> {
     tp: TermPos,
+    keymap: &'a Keymap,
+    /// Keys typed so far of a multi-key binding
+    pending: Vec<KeyEvent>,
+    kill_ring: KillRing,
+    undo: UndoStack,
+    last_action: LastAction,
+    /// Only set in vi editing mode
+    vi: Option<ViState>,
+    /// Text received since the start of a bracketed paste
+    paste: Option<Vec<char>>,
 }
 
-impl TextInput {
-    pub fn new(stdout: &mut termion::raw::RawTerminal<std::io::Stdout>) -> TextInput {
-        let ti = TextInput {
-            tp: TermPos::new(stdout),
+impl<'a> TextInput<'a> {
+    pub fn new(stdout: &mut termion::raw::RawTerminal<std::io::Stdout>, edit_mode: EditMode, prompt: Prompt, keymap: &'a Keymap) -> TextInput<'a> {
+        let vi = match edit_mode {
+            EditMode::Emacs => None,
+            EditMode::Vi => Some(ViState::new()),
+        };
+        let indicator = vi.as_ref().map_or("", |vi| vi.mode_indicator());
+        let mut ti = TextInput {
+            tp: TermPos::new(stdout, prompt, indicator),
+            keymap,
+            pending: Vec::new(),
+            kill_ring: KillRing::new(),
+            undo: UndoStack::new(),
+            last_action: LastAction::Other,
+            vi,
+            paste: None,
         };
         ti._display_buffer();
         ti
     }
 
-    pub fn handle_event(&mut self, event: TrueEvent) -> TextInputEvent {
+    /// `history` gives the suggestion displayed after the buffer
+    pub fn handle_event(&mut self, event: TrueEvent, history: &History) -> TextInputEvent {
+        let res = self._handle_event(event);
+        if self.paste.is_some() {
+            return res;
+        }
+        match res {
+            TextInputEvent::None => {
+                self.tp.suggestion = self._suggestion(history);
+                self._display_buffer();
+            },
+            TextInputEvent::Buffer(_, _) => {
+                // Output goes below the whole input, not below the cursor line
+                self.tp.suggestion.clear();
+                self.tp.end();
+                self._display_buffer();
+            },
+            _ => self.tp.suggestion.clear(),
+        }
+        res
+    }
+
+    fn _handle_event(&mut self, event: TrueEvent) -> TextInputEvent {
+        if let Some(paste) = &mut self.paste {
+            match event {
+                TrueEvent::KeyEvent(KeyEvent::Key(Key::Char(c))) => paste.push(c),
+                TrueEvent::PasteEnd => self._paste(),
+                _ => {}
+            }
+            return TextInputEvent::None;
+        }
+        match event {
+            TrueEvent::PasteStart => {
+                self.paste = Some(Vec::new());
+                return TextInputEvent::None;
+            },
+            TrueEvent::Resize => {
+                self.tp.resize();
+                return TextInputEvent::None;
+            },
+            TrueEvent::MouseEvent(MouseEvent::Press(MouseButton::Left, x, y)) => {
+                self.tp.click(x, y);
+                self.last_action = LastAction::Other;
+                self._clamp_vi_cursor();
+                return TextInputEvent::None;
+            },
+            _ => {}
+        }
         if let TrueEvent::KeyEvent(ke) = event {
-            match ke {
-                KeyEvent::Key(k) => {
-                    match k {
-                        Key::Char(c) => {
-                            if c == '\n' {
-                                let ret = self.tp.buffer.iter().fold(String::new(), |mut acc, &arg| { acc.push(arg); acc });
-                                return TextInputEvent::Buffer(self.tp.buffer.clone(), ret);
-                            } else {
-                                self.tp.char(c);
-                            }
-                        },
-                        Key::Backspace => self.tp.backspace(),
-                        Key::Delete => self.tp.delete(),
-                        Key::Left => self.tp.left(),
-                        Key::Right => self.tp.right(),
-                        Key::Up => self.tp.up(),
-                        Key::Down => self.tp.down(),
-                        Key::Home => self.tp.beg(),
-                        Key::End => self.tp.end(),
-                        Key::PageUp => self.tp.word_left(),
-                        Key::PageDown => self.tp.word_right(),
-                        _ => {}
-                    }
-                },
-                KeyEvent::Ctrl(k) => {
-                    match k {
-                        Key::Char(c) => {
-                            match c {
-                                'c' | 'd' => {
-                                    print!("Quit\r\n");
-                                    return TextInputEvent::Quit;
-                                }
-                                'a' => self.tp.beg(),
-                                'e' => self.tp.end(),
-                                'l' => self.tp.clear_term(),
-                                _ => {}
-                            }
-                        }
-                        Key::Left => self.tp.word_left(),
-                        Key::Right => self.tp.word_right(),
-                        Key::Up => return TextInputEvent::HistoryPrev,
-                        Key::Down => return TextInputEvent::HistoryNext,
-                        _ => {}
-                    }
+            if let Some(mode) = self.vi.as_ref().map(|vi| vi.mode) {
+                match &ke {
+                    KeyEvent::Key(Key::Esc) => {
+                        self._vi_escape(mode);
+                        self.last_action = LastAction::Other;
+                        return TextInputEvent::None;
+                    },
+                    &KeyEvent::Key(Key::Char(c)) if c != '\n' && mode != ViMode::Insert => {
+                        let command = self.vi.as_mut().unwrap().key(c);
+                        self.last_action = LastAction::Other;
+                        let res = self._handle_vi_command(command);
+                        self._clamp_vi_cursor();
+                        return res;
+                    },
+                    KeyEvent::Ctrl(Key::Char('r')) if mode != ViMode::Insert => {
+                        self._redo();
+                        self._clamp_vi_cursor();
+                        self.last_action = LastAction::Other;
+                        return TextInputEvent::None;
+                    },
+                    _ => {}
+                }
+            }
+            self.pending.push(ke);
+            let bound = match self.keymap.lookup(Context::Input, &self.pending) {
+                Lookup::Pending => return TextInputEvent::None,
+                Lookup::Action(action) => Some(action),
+                Lookup::Unbound => None,
+            };
+            let keys = std::mem::take(&mut self.pending);
+            let (res, action) = match (bound, &keys[..]) {
+                (Some(action), _) => self._run(action),
+                // Unbound characters insert themselves, other keys and unknown sequences are ignored
+                (None, &[KeyEvent::Key(Key::Char(c))]) => {
+                    self._save_undo(LastAction::Typing);
+                    self.tp.char(c);
+                    (TextInputEvent::None, LastAction::Typing)
                 },
-                _ => {}
+                (None, _) => (TextInputEvent::None, LastAction::Other),
+            };
+            if res != TextInputEvent::None {
+                return res;
             }
-            self._display_buffer();
+            self.last_action = action;
+            self._clamp_vi_cursor();
         }
         TextInputEvent::None
     }
 
+    /// Returns the event for the caller and the action for undo grouping and yank-pop
+    fn _run(&mut self, action: Action) -> (TextInputEvent, LastAction) {
+        let mut last = LastAction::Other;
+        // Moving right at the end accepts the suggestion
+        if !self.tp.suggestion.is_empty() {
+            match action {
+                Action::ForwardChar | Action::EndOfLine => {
+                    self._accept_suggestion(false);
+                    return (TextInputEvent::None, last);
+                },
+                Action::ForwardWord => {
+                    self._accept_suggestion(true);
+                    return (TextInputEvent::None, last);
+                },
+                _ => {},
+            }
+        }
+        match action {
+            Action::AcceptLine => {
+                let ret = self.tp.buffer.iter().fold(String::new(), |mut acc, &arg| { acc.push(arg); acc });
+                return (TextInputEvent::Buffer(self.tp.buffer.clone(), ret), last);
+            },
+            Action::Quit => {
+                print!("Quit\r\n");
+                return (TextInputEvent::Quit, last);
+            },
+            Action::EditInEditor => return (TextInputEvent::Edit, last),
+            Action::HistoryPrev => return (TextInputEvent::HistoryPrev, last),
+            Action::HistoryNext => return (TextInputEvent::HistoryNext, last),
+            Action::BackwardChar => self.tp.left(),
+            Action::ForwardChar => self.tp.right(),
+            Action::BackwardWord => self.tp.word_left(),
+            Action::ForwardWord => self.tp.word_right(),
+            Action::BeginningOfLine => self.tp.beg(),
+            Action::EndOfLine => self.tp.end(),
+            Action::PreviousLine => self.tp.up(),
+            Action::NextLine => self.tp.down(),
+            Action::BackwardDeleteChar => {
+                self._save_undo(LastAction::Deleting);
+                self.tp.backspace();
+                last = LastAction::Deleting;
+            },
+            Action::DeleteChar => {
+                self._save_undo(LastAction::Deleting);
+                self.tp.delete();
+                last = LastAction::Deleting;
+            },
+            Action::KillWord => last = self._kill(self.tp.current_index, self.tp.word_end_after(), KillDirection::Forward),
+            Action::BackwardKillWord => last = self._kill(self.tp.word_start_before(), self.tp.current_index, KillDirection::Backward),
+            Action::KillLine => last = self._kill(self.tp.current_index, self.tp.buffer.len(), KillDirection::Forward),
+            Action::BackwardKillLine => last = self._kill(0, self.tp.current_index, KillDirection::Backward),
+            Action::Yank => last = self._yank(),
+            Action::YankPop => last = self._yank_pop(),
+            Action::TransposeChars => {
+                self._save_undo(LastAction::Other);
+                self.tp.transpose();
+            },
+            Action::Undo => self._undo(),
+            Action::Redo => self._redo(),
+            Action::ClearScreen => self.tp.clear_term(),
+            Action::JumpToMatching => self._jump_to_matching(),
+            _ => {},
+        }
+        (TextInputEvent::None, last)
+    }
+
     pub fn data(&self) -> Vec<char> {
         self.tp.buffer.clone()
     }
 
+    /// Moves the input line to the current cursor line, after something else was printed
+    pub fn reset_position(&mut self, stdout: &mut termion::raw::RawTerminal<std::io::Stdout>) {
+        self.tp.y = stdout.cursor_pos().unwrap().1;
+        self._display_buffer();
+    }
+
     pub fn set_data(&mut self, d: Vec<char>) {
+        self._save_undo(LastAction::HistoryRecall);
+        self.last_action = LastAction::HistoryRecall;
         self.tp.buffer = d;
+        self.tp.suggestion.clear();
         self.tp.end();
+        self._clamp_vi_cursor();
         self._display_buffer();
     }
 
-    fn _display_buffer(&self) {
-        print!("{}{}{}{}{}",
+    /// Pasted text is inserted as a single edit, newlines included
+    fn _paste(&mut self) {
+        if let Some(text) = self.paste.take() {
+            self._save_undo(LastAction::Other);
+            self.tp.insert(&text);
+            self.last_action = LastAction::Other;
+        }
+    }
+
+    /// Rest of the most recent history entry starting with the buffer, only while typing at its end
+    fn _suggestion(&self, history: &History) -> Vec<char> {
+        let typing = self.vi.as_ref().is_none_or(|vi| vi.mode == ViMode::Insert);
+        if !typing || self.tp.buffer.is_empty() || self.tp.current_index < self.tp.buffer.len() {
+            return Vec::new();
+        }
+        history.suggestion(&self.tp.buffer).unwrap_or_default()
+    }
+
+    /// Bracket or quote under the cursor, or just before it, and the one it matches
+    fn _matching_pair(&self, pairs: &lexer::Pairs) -> Option<(usize, usize)> {
+        let index = self.tp.current_index;
+        let candidates = if index > 0 { vec![index, index - 1] } else { vec![index] };
+        candidates.into_iter().find_map(|i| pairs.matching(i).map(|j| (i, j)))
+    }
+
+    fn _jump_to_matching(&mut self) {
+        if let Some((_, j)) = self._matching_pair(&lexer::pairs(&self.tp.buffer)) {
+            self.tp.move_to(j);
+        }
+    }
+
+    /// Inserts the suggestion, or only its first word
+    fn _accept_suggestion(&mut self, word: bool) {
+        let suggestion = &self.tp.suggestion;
+        let mut len = suggestion.len();
+        if word {
+            let start = suggestion.iter().position(|c| !c.is_whitespace()).unwrap_or(len);
+            len = suggestion[start..].iter().position(|c| c.is_whitespace()).map_or(len, |p| start + p);
+        }
+        let text = suggestion[..len].to_vec();
+        self._save_undo(LastAction::Other);
+        self.tp.insert(&text);
+    }
+
+    fn _kill(&mut self, start: usize, end: usize, direction: KillDirection) -> LastAction {
+        if start == end {
+            return self.last_action;
+        }
+        self._save_undo(LastAction::Kill);
+        let text = self.tp.remove(start, end);
+        self.kill_ring.kill(text, direction, self.last_action == LastAction::Kill);
+        LastAction::Kill
+    }
+
+    fn _yank(&mut self) -> LastAction {
+        if let Some(text) = self.kill_ring.yank().map(|t| t.to_vec()) {
+            let start = self.tp.current_index;
+            self._save_undo(LastAction::Other);
+            self.tp.insert(&text);
+            LastAction::Yank(start, text.len())
+        } else {
+            LastAction::Other
+        }
+    }
+
+    /// Replaces the text that was just yanked with the previous kill
+    fn _yank_pop(&mut self) -> LastAction {
+        if let LastAction::Yank(start, len) = self.last_action {
+            if let Some(text) = self.kill_ring.rotate() {
+                self.tp.buffer.drain(start..start + len);
+                self.tp.move_to(start);
+                self.tp.insert(text);
+                return LastAction::Yank(start, text.len());
+            }
+        }
+        LastAction::Other
+    }
+
+    /// Typing runs, consecutive character deletions and history browsing are undone as a single edit
+    fn _save_undo(&mut self, action: LastAction) {
+        let merge = self.last_action == action && matches!(action, LastAction::Typing | LastAction::Deleting | LastAction::HistoryRecall);
+        self.undo.save(&self.tp.buffer, self.tp.current_index, merge);
+    }
+
+    fn _undo(&mut self) {
+        if let Some((buffer, index)) = self.undo.undo(&self.tp.buffer, self.tp.current_index) {
+            self.tp.buffer = buffer;
+            self.tp.move_to(index);
+        }
+    }
+
+    fn _redo(&mut self) {
+        if let Some((buffer, index)) = self.undo.redo(&self.tp.buffer, self.tp.current_index) {
+            self.tp.buffer = buffer;
+            self.tp.move_to(index);
+        }
+    }
+
+    fn _handle_vi_command(&mut self, command: ViCommand) -> TextInputEvent {
+        let index = self.tp.current_index;
+        match command {
+            ViCommand::Pending | ViCommand::Invalid => {},
+            ViCommand::Move(motion, count) => {
+                if let Some(i) = vi::motion_target(&self.tp.buffer, index, &motion, count) {
+                    self.tp.move_to(i);
+                }
+            },
+            ViCommand::Operate(operator, motion, count) => {
+                if let Some((start, end)) = vi::motion_range(&self.tp.buffer, index, operator, &motion, count) {
+                    self._vi_operate(operator, start, end);
+                }
+            },
+            ViCommand::OperateSelection(operator) => {
+                let (start, end) = self._vi_selection();
+                self._vi_operate(operator, start, end);
+            },
+            ViCommand::Put { before, count } => {
+                if let Some(text) = self.kill_ring.yank().map(|t| t.to_vec()) {
+                    self._save_undo(LastAction::Other);
+                    if !before && index < self.tp.buffer.len() {
+                        self.tp.right();
+                    }
+                    for _ in 0..count {
+                        self.tp.insert(&text);
+                    }
+                    self.tp.left();
+                }
+            },
+            ViCommand::Insert(position) => {
+                match position {
+                    InsertPosition::BeforeCursor => {},
+                    InsertPosition::AfterCursor => self.tp.right(),
+                    InsertPosition::LineStart => self.tp.beg(),
+                    InsertPosition::LineEnd => self.tp.end(),
+                }
+                self._set_vi_mode(ViMode::Insert);
+            },
+            ViCommand::ToggleVisual => {
+                if self.vi.as_ref().unwrap().mode == ViMode::Visual {
+                    self._set_vi_mode(ViMode::Normal);
+                } else {
+                    self.vi.as_mut().unwrap().visual_start = index;
+                    self._set_vi_mode(ViMode::Visual);
+                }
+            },
+            ViCommand::Undo(count) => {
+                for _ in 0..count {
+                    self._undo();
+                }
+            },
+            ViCommand::HistoryPrev => return TextInputEvent::HistoryPrev,
+            ViCommand::HistoryNext => return TextInputEvent::HistoryNext,
+        }
+        TextInputEvent::None
+    }
+
+    fn _vi_operate(&mut self, operator: Operator, start: usize, end: usize) {
+        if operator == Operator::Yank {
+            self.kill_ring.kill(self.tp.buffer[start..end].to_vec(), KillDirection::Forward, false);
+            self.tp.move_to(start);
+        } else if start < end {
+            self._save_undo(LastAction::Other);
+            let text = self.tp.remove(start, end);
+            self.kill_ring.kill(text, KillDirection::Forward, false);
+        }
+        if operator == Operator::Change {
+            // The change and the text typed afterwards are undone together
+            self.last_action = LastAction::Typing;
+            self._set_vi_mode(ViMode::Insert);
+        } else {
+            self._set_vi_mode(ViMode::Normal);
+        }
+    }
+
+    fn _vi_escape(&mut self, mode: ViMode) {
+        self.vi.as_mut().unwrap().reset();
+        if mode == ViMode::Insert {
+            self.tp.left();
+        }
+        self._set_vi_mode(ViMode::Normal);
+        self._clamp_vi_cursor();
+    }
+
+    /// Selected `[start, end)` range in visual mode
+    fn _vi_selection(&self) -> (usize, usize) {
+        let start = self.vi.as_ref().map(|vi| vi.visual_start).unwrap_or(self.tp.current_index);
+        let end = std::cmp::min(std::cmp::max(start, self.tp.current_index) + 1, self.tp.buffer.len());
+        (std::cmp::min(start, self.tp.current_index), end)
+    }
+
+    fn _set_vi_mode(&mut self, mode: ViMode) {
+        if let Some(vi) = &mut self.vi {
+            vi.mode = mode;
+            self.tp.indicator = vi.mode_indicator();
+        }
+    }
+
+    /// Outside of insert mode, the cursor stays on a character like in vi
+    fn _clamp_vi_cursor(&mut self) {
+        if let Some(vi) = &self.vi {
+            if vi.mode != ViMode::Insert && self.tp.current_index > 0 && self.tp.current_index == self.tp.buffer.len() {
+                self.tp.left();
+            }
+        }
+    }
+
+    fn _display_buffer(&mut self) {
+        let text = self.tp.displayed();
+        let (layout, shown, (x, y)) = self.tp.scroll(&text);
+        let prompts: Vec<String> = self.tp.prompts(&text).into_iter().map(|p| p.0).collect();
+        let buffer_len = self.tp.buffer.len();
+        let pairs = lexer::pairs(&self.tp.buffer);
+        let matching = self._matching_pair(&pairs);
+        let selection = match self.vi.as_ref().map(|vi| vi.mode) {
+            Some(ViMode::Visual) => Some(self._vi_selection()),
+            _ => None,
+        };
+        let style = |i: usize| match selection {
+            Some((start, end)) if i >= start && i < end => Some(termion::style::Invert.to_string()),
+            _ if i >= buffer_len => Some(termion::style::Faint.to_string()),
+            _ if matching.is_some_and(|(a, b)| i == a || i == b) => Some(format!("{}{}", termion::style::Bold, termion::color::Fg(termion::color::Cyan))),
+            _ if pairs.unmatched.contains(&i) => Some(termion::color::Fg(termion::color::Red).to_string()),
+            _ => None,
+        };
+        print!("{}{}{}{}",
                termion::cursor::Goto(1, self.tp.y),
                termion::clear::AfterCursor,
-               PROMPT, self.tp.buffer.iter().fold(String::new(), |mut acc, &arg| { acc.push(arg); acc }),
-               termion::cursor::Goto(self.tp.x, self.tp.y + self.tp.d_y));
+               layout.render(&text, &prompts, self.tp.top, shown, style),
+               termion::cursor::Goto(x, y));
         std::io::stdout().flush().unwrap();
     }
 }
@@ -1,44 +1,189 @@
 use termion::screen::AlternateScreen;
+use termion::event::MouseButton;
+use termion::style::{Bold, Invert, Reset};
+use termion::color;
+use std::collections::HashSet;
 use std::io::{Stdout, Write};
 use std::cmp::{min, max};
-use crate::ui::event::{TrueEvent, KeyEvent, Key};
+use crate::export::{self, Format};
+use crate::regex::Regex;
+use crate::sql::{QueryResult, HEADER_LINES};
+use crate::ui::event::{TrueEvent, KeyEvent, Key, MouseEvent};
+use crate::ui::clipboard;
+use crate::ui::inspector::Inspector;
+use crate::ui::rows::{self, SortKey, Filter};
+use crate::ui::keymap::{Keymap, Context, Lookup, Action};
+
+/// Rows scrolled by a turn of the mouse wheel
+const WHEEL_LINES: usize = 3;
+
+/// A search being typed after `/` or `?`
+struct SearchPrompt {
+    backward: bool,
+    regex: bool,
+    text: Vec<char>,
+}
+
+impl SearchPrompt {
+    fn description(&self) -> String {
+        format!("{}{}{}", if self.regex { "regex " } else { "" }, if self.backward { '?' } else { '/' }, self.text.iter().collect::<String>())
+    }
+}
+
+enum Prompt {
+    Search(SearchPrompt),
+    /// Filter on a column, typed after `&`
+    Filter(usize, Vec<char>),
+    /// File to export the rows to, Tab changes the format
+    Export(Format, Vec<char>),
+    /// The export file exists, `y` writes over it
+    Overwrite(Format, String),
+}
+
+/// What a key does to the text of a prompt
+enum PromptKey {
+    Accept,
+    Cancel,
+    Typed,
+}
+
+/// Enter accepts the text, Esc or Backspace once it is empty cancels it, Tab is left to the prompt
+fn _type_in(text: &mut Vec<char>, key: KeyEvent) -> PromptKey {
+    match key {
+        KeyEvent::Key(Key::Char('\n')) => PromptKey::Accept,
+        KeyEvent::Key(Key::Esc) => PromptKey::Cancel,
+        KeyEvent::Key(Key::Backspace) if text.pop().is_none() => PromptKey::Cancel,
+        KeyEvent::Key(Key::Char(c)) if c != '\t' => {
+            text.push(c);
+            PromptKey::Typed
+        },
+        _ => PromptKey::Typed,
+    }
+}
+
+struct Search {
+    prompt: SearchPrompt,
+    /// Row and `[start, end)` in bytes of every match, in order
+    matches: Vec<(usize, usize, usize)>,
+    current: Option<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Highlight {
+    Selected,
+    Match,
+    CurrentMatch,
+    Changed,
+}
+
+impl Highlight {
+    fn style(&self) -> String {
+        match self {
+            Highlight::Selected => Invert.to_string(),
+            Highlight::Match => format!("{}{}", color::Fg(color::Black), color::Bg(color::Yellow)),
+            Highlight::CurrentMatch => format!("{}{}{}", Bold, color::Fg(color::Black), color::Bg(color::Cyan)),
+            Highlight::Changed => format!("{}{}", Bold, color::Fg(color::Red)),
+        }
+    }
+}
 
 pub struct TextView<'a> {
-    data: &'a[String],
+    result: &'a QueryResult,
+    /// Stays at the top of the screen
+    header: Vec<String>,
+    /// One line per row of the result
+    lines: Vec<String>,
+    /// Rows shown, sorted and filtered, as indexes in the result
+    order: Vec<usize>,
+    sort: Vec<SortKey>,
+    /// Column and filter
+    filters: Vec<(usize, Filter)>,
+    /// One line per row shown
+    data: Vec<String>,
+    /// `[start, end)` of every column in the lines
+    bounds: Vec<(usize, usize)>,
+    /// Number of columns staying at the left of the screen
+    frozen: usize,
+    /// Row and column of the selected cell, moved by the cell cursor keys
+    selected: Option<(usize, usize)>,
+    /// Full value of the selected cell
+    inspector: Option<Inspector>,
+    /// Cells of the result that differ from another result, row and column
+    changed: HashSet<(usize, usize)>,
+    prompt: Option<Prompt>,
+    search: Option<Search>,
+    /// Displayed in the status line until the next key
+    message: Option<String>,
+    /// Number typed before `g` or `G` to go to a row
+    count: Option<usize>,
     x: usize,
     y: usize,
     max_x: u16,
     max_y: u16,
     max_len: usize,
+    keymap: &'a Keymap,
+    /// Keys typed so far of a multi-key binding
+    pending: Vec<KeyEvent>,
+    /// Left when the view is dropped, none in tests
     #[allow(dead_code)]
-    screen: AlternateScreen<Stdout>,
+    screen: Option<AlternateScreen<Stdout>>,
 }
 
 impl<'a> TextView<'a> {
-    pub fn new(data: &'a[String]) -> TextView<'a> {
-        let (max_x, max_y) = termion::terminal_size().unwrap();
+    pub fn new(result: &'a QueryResult, keymap: &'a Keymap, frozen: usize) -> TextView<'a> {
+        let size = termion::terminal_size().unwrap();
+        TextView::_new(result, keymap, frozen, size, Some(AlternateScreen::from(std::io::stdout())))
+    }
+
+    fn _new(result: &'a QueryResult, keymap: &'a Keymap, frozen: usize, (max_x, max_y): (u16, u16),
+            screen: Option<AlternateScreen<Stdout>>) -> TextView<'a> {
+        let mut data = result.lines();
+        let header = data.drain(..HEADER_LINES).collect();
+        let order = (0..data.len()).collect();
         let mut tv = TextView {
+            result,
+            header,
+            lines: data.clone(),
+            order,
+            sort: Vec::new(),
+            filters: Vec::new(),
             data,
+            bounds: result.column_bounds(),
+            frozen: min(frozen, result.columns.len()),
+            selected: None,
+            inspector: None,
+            changed: HashSet::new(),
+            prompt: None,
+            search: None,
+            message: None,
+            count: None,
             x: 0,
             y: 0,
             max_x,
             max_y,
             max_len: 0,
-            screen: AlternateScreen::from(std::io::stdout())
+            keymap,
+            pending: Vec::new(),
+            screen,
         };
         tv.max_len = tv._get_current_slice_max_len();
         tv
     }
 
+    /// Highlights cells, e.g. the differences between two results
+    pub fn mark_changed(&mut self, cells: HashSet<(usize, usize)>) {
+        self.changed = cells;
+    }
+
     pub fn display(&self) {
         print!("{}{}", termion::cursor::Goto(1,1), termion::clear::All);
-        let rows = self._get_row_slice();
-        for (i, string) in rows.iter().enumerate() {
-            let columns = self._get_column_slice(string);
-            print!("{}", columns);
-            if i < rows.len() - 1 {
-                print!("\r\n");
-            }
+        let header = self.header.iter().map(|line| self._render_line(None, line));
+        let rows = self._get_row_slice().iter().enumerate().map(|(i, line)| self._render_line(Some(self.y + i), line));
+        let lines: Vec<String> = header.chain(rows).take((self.max_y as usize).saturating_sub(1)).collect();
+        print!("{}", lines.join("\r\n"));
+        self._display_status();
+        if let Some(inspector) = &self.inspector {
+            print!("{}", inspector.render(self.max_x, self.max_y));
         }
         std::io::stdout().flush().unwrap();
     }
@@ -46,42 +191,157 @@ impl<'a> TextView<'a> {
     pub fn handle_event(&mut self, event: TrueEvent) -> bool {
         let mut display = false;
         let mut again = true;
+        if let TrueEvent::Resize = event {
+            self._resize();
+            display = true;
+        }
+        if let TrueEvent::MouseEvent(me) = event {
+            match me {
+                MouseEvent::Press(MouseButton::WheelUp, _, _) => self._scroll_to(self.y.saturating_sub(WHEEL_LINES)),
+                MouseEvent::Press(MouseButton::WheelDown, _, _) => self._scroll_to(self.y + WHEEL_LINES),
+                MouseEvent::Press(MouseButton::Left, x, y) => self._select_at(x, y),
+                _ => {},
+            }
+            display = true;
+        }
         if let TrueEvent::KeyEvent(ke) = event {
-            match ke {
-                KeyEvent::Key(k) => {
-                    match k {
-                        Key::Down => {
-                            if self.y + (self.max_y as usize) < self.data.len() {
-                                self.y += 1;
-                                display = true;
-                                self.max_len = self._get_current_slice_max_len();
-                            }
-                        },
-                        Key::Up => {
-                            if self.y > 0 {
-                                self.y -= 1;
-                                display = true;
-                                self.max_len = self._get_current_slice_max_len();
-                            }
-                        },
-                        Key::Right => {
-                            if self.x + (self.max_x as usize) < self.max_len {
-                                self.x += 1;
-                                display = true;
-                            }
-                        },
-                        Key::Left => {
-                            if self.x > 0 {
-                                self.x -= 1;
-                                display = true;
-                            }
-                        },
+            self.message = None;
+            if let Some(inspector) = &mut self.inspector {
+                if !inspector.handle_key(ke, self.max_x, self.max_y) {
+                    self.inspector = None;
+                }
+                self.display();
+                return true;
+            }
+            if self.prompt.is_some() {
+                self._edit_prompt(ke);
+                self.display();
+                return true;
+            }
+            self.pending.push(ke);
+            let bound = match self.keymap.lookup(Context::Pager, &self.pending) {
+                Lookup::Pending => return true,
+                Lookup::Action(action) => Some(action),
+                Lookup::Unbound => None,
+            };
+            let keys = std::mem::take(&mut self.pending);
+            let count = self.count.take();
+            display = true;
+            match (bound, &keys[..]) {
+                (Some(Action::PagerDown), _) if self.y + self._height() < self.data.len() => {
+                    self.y += 1;
+                    self.max_len = self._get_current_slice_max_len();
+                },
+                (Some(Action::PagerUp), _) if self.y > 0 => {
+                    self.y -= 1;
+                    self.max_len = self._get_current_slice_max_len();
+                },
+                (Some(Action::PagerRight), _) if self.x + (self.max_x as usize) < self.max_len => {
+                    self.x += 1;
+                },
+                (Some(Action::PagerLeft), _) if self.x > 0 => {
+                    self.x -= 1;
+                },
+                (Some(action @ Action::PagerSearchForward), _) | (Some(action @ Action::PagerSearchBackward), _) => {
+                    // Keeps the kind of pattern of the last search
+                    let regex = self.search.as_ref().is_some_and(|s| s.prompt.regex);
+                    self.prompt = Some(Prompt::Search(SearchPrompt { backward: action == Action::PagerSearchBackward, regex, text: Vec::new() }));
+                },
+                (Some(action @ Action::PagerNextMatch), _) | (Some(action @ Action::PagerPreviousMatch), _) => {
+                    self._next_match(action == Action::PagerPreviousMatch);
+                },
+                (Some(Action::PagerPageUp), _) => self._scroll_to(self.y.saturating_sub(self._height())),
+                (Some(Action::PagerPageDown), _) => self._scroll_to(self.y + self._height()),
+                (Some(Action::PagerHalfPageUp), _) => self._scroll_to(self.y.saturating_sub(self._height() / 2)),
+                (Some(Action::PagerHalfPageDown), _) => self._scroll_to(self.y + self._height() / 2),
+                // With a count, both go to that row
+                (Some(Action::PagerTop), _) | (Some(Action::PagerBottom), _) if count.is_some() => {
+                    self._scroll_to(count.unwrap().saturating_sub(1));
+                },
+                (Some(Action::PagerTop), _) => self._scroll_to(0),
+                (Some(Action::PagerBottom), _) => self._scroll_to(self.data.len()),
+                (Some(Action::PagerNextColumn), _) => {
+                    let pinned = self._pinned_width();
+                    if let Some(&(start, _)) = self.bounds.iter().skip(self.frozen).find(|b| b.0 > pinned + self.x) {
+                        self.x = min(start - pinned, self.max_len.saturating_sub(self.max_x as usize));
+                    }
+                },
+                (Some(Action::PagerPreviousColumn), _) => {
+                    let pinned = self._pinned_width();
+                    if let Some(&(start, _)) = self.bounds.iter().skip(self.frozen).rev().find(|b| b.0 < pinned + self.x) {
+                        self.x = start - pinned;
+                    }
+                },
+                // Without a count, freezes the first column or none
+                (Some(Action::PagerFreezeColumns), _) => {
+                    let frozen = count.unwrap_or(if self.frozen > 0 { 0 } else { 1 });
+                    self.frozen = min(frozen, self.bounds.len());
+                },
+                (Some(Action::PagerCellUp), _) => {
+                    let (row, column) = self._cursor();
+                    self._select(row.saturating_sub(1), column);
+                },
+                (Some(Action::PagerCellDown), _) => {
+                    let (row, column) = self._cursor();
+                    self._select(row + 1, column);
+                },
+                (Some(Action::PagerCellLeft), _) => {
+                    let (row, column) = self._cursor();
+                    self._select(row, column.saturating_sub(1));
+                },
+                (Some(Action::PagerCellRight), _) => {
+                    let (row, column) = self._cursor();
+                    self._select(row, column + 1);
+                },
+                (Some(Action::PagerInspect), _) if !self.data.is_empty() && !self.bounds.is_empty() => {
+                    let (row, column) = self._cursor();
+                    self._select(row, column);
+                    let value = self.result.rows[self.order[row]][column].as_deref();
+                    self.inspector = Some(Inspector::new(&self.result.columns[column], value));
+                },
+                // Sorting again on the first key reverses it, another column becomes the first key
+                (Some(Action::PagerSort), _) if !self.bounds.is_empty() => {
+                    let (_, column) = self._cursor();
+                    match self.sort.first_mut() {
+                        Some(key) if key.column == column => key.descending = !key.descending,
                         _ => {
-                            print!("{}", termion::screen::ToMainScreen);
-                            std::io::stdout().flush().unwrap();
-                            again = false;
-                        }
+                            self.sort.retain(|key| key.column != column);
+                            self.sort.insert(0, SortKey { column, descending: false });
+                        },
                     }
+                    self._arrange();
+                    self.message = Some(self._sort_description());
+                },
+                (Some(Action::PagerSortReset), _) => {
+                    self.sort.clear();
+                    self._arrange();
+                    self.message = Some(String::from("Rows in the order of the server"));
+                },
+                (Some(Action::PagerFilter), _) if !self.bounds.is_empty() => {
+                    let (_, column) = self._cursor();
+                    self.prompt = Some(Prompt::Filter(column, Vec::new()));
+                },
+                (Some(Action::PagerExport), _) => self.prompt = Some(Prompt::Export(Format::Csv, Vec::new())),
+                (Some(Action::PagerYankCell), _) if !self.data.is_empty() && !self.bounds.is_empty() => {
+                    let (row, column) = self._cursor();
+                    self._select(row, column);
+                    let value = self.result.rows[self.order[row]][column].clone().unwrap_or_default();
+                    self._yank(&value, "cell");
+                },
+                (Some(Action::PagerYankRowCsv), _) => self._yank_row(Format::Csv),
+                (Some(Action::PagerYankRowJson), _) => self._yank_row(Format::Json),
+                (Some(Action::PagerYankRowInsert), _) => self._yank_row(Format::Insert),
+                (Some(Action::PagerYankResultCsv), _) => self._yank_result(Format::Csv),
+                (Some(Action::PagerYankResultJson), _) => self._yank_result(Format::Json),
+                (Some(Action::PagerYankResultInsert), _) => self._yank_result(Format::Insert),
+                (None, &[KeyEvent::Key(Key::Char(c))]) if c.is_ascii_digit() => {
+                    let digit = c.to_digit(10).unwrap() as usize;
+                    self.count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                },
+                (Some(Action::PagerQuit), _) => {
+                    print!("{}", termion::screen::ToMainScreen);
+                    std::io::stdout().flush().unwrap();
+                    again = false;
                 },
                 _ => {}
             }
@@ -94,12 +354,405 @@ impl<'a> TextView<'a> {
         again
     }
 
+    /// Rows shown, the header and the status line take the rest of the terminal
+    fn _height(&self) -> usize {
+        max((self.max_y as usize).saturating_sub(self.header.len() + 1), 1)
+    }
+
+    /// Width of the frozen columns with their separators
+    fn _pinned_width(&self) -> usize {
+        match self.frozen {
+            0 => 0,
+            n => self.bounds[n - 1].1 + 1,
+        }
+    }
+
+    /// Keeps the viewport inside the data with the new terminal size
+    fn _resize(&mut self) {
+        let (max_x, max_y) = termion::terminal_size().unwrap_or((self.max_x, self.max_y));
+        self.max_x = max_x;
+        self.max_y = max_y;
+        self.y = min(self.y, self.data.len().saturating_sub(self._height()));
+        self.max_len = self._get_current_slice_max_len();
+        self.x = min(self.x, self.max_len.saturating_sub(max_x as usize));
+    }
+
+    /// Shows the rows from `y`, or the last screen if there are not enough of them
+    fn _scroll_to(&mut self, y: usize) {
+        self.y = min(y, self.data.len().saturating_sub(self._height()));
+        self.max_len = self._get_current_slice_max_len();
+    }
+
+    /// Selects the cell displayed at the terminal position, clicks on the header are ignored
+    fn _select_at(&mut self, x: u16, y: u16) {
+        let line = (y as usize).saturating_sub(1);
+        if line < self.header.len() || line >= self.header.len() + self._height() {
+            return;
+        }
+        let row = self.y + line - self.header.len();
+        let column = (x as usize).saturating_sub(1);
+        let pinned = self._pinned_width();
+        let position = if column < pinned { column } else { column + self.x };
+        if row >= self.data.len() {
+            return;
+        }
+        if let Some(column) = self.bounds.iter().position(|&(start, end)| start <= position && position <= end) {
+            self.selected = Some((row, column));
+        }
+    }
+
+    /// The selected cell, or the first one on the screen
+    fn _cursor(&self) -> (usize, usize) {
+        self.selected.unwrap_or_else(|| {
+            let pinned = self._pinned_width();
+            let column = if self.frozen > 0 {
+                0
+            } else {
+                self.bounds.iter().position(|b| b.1 > pinned + self.x).unwrap_or(0)
+            };
+            (self.y, column)
+        })
+    }
+
+    /// Selects a cell and scrolls to show it, the position is clamped to the result
+    fn _select(&mut self, row: usize, column: usize) {
+        if self.data.is_empty() || self.bounds.is_empty() {
+            return;
+        }
+        let row = min(row, self.data.len() - 1);
+        let column = min(column, self.bounds.len() - 1);
+        self.selected = Some((row, column));
+
+        let height = self._height();
+        if row < self.y {
+            self._scroll_to(row);
+        } else if row >= self.y + height {
+            self._scroll_to(row + 1 - height);
+        }
+        if column >= self.frozen {
+            let (start, end) = self.bounds[column];
+            let pinned = self._pinned_width();
+            // The separator is shown too, the start wins for cells wider than the screen
+            if end + 1 > self.x + self.max_x as usize {
+                self.x = end + 1 - self.max_x as usize;
+            }
+            if start < pinned + self.x {
+                self.x = start.saturating_sub(pinned);
+            }
+        }
+    }
+
+    fn _edit_prompt(&mut self, key: KeyEvent) {
+        match self.prompt.take() {
+            None => {},
+            Some(Prompt::Overwrite(format, path)) => {
+                if matches!(key, KeyEvent::Key(Key::Char('y')) | KeyEvent::Key(Key::Char('Y'))) {
+                    self._export(&path, format, true);
+                } else {
+                    self.message = Some(format!("{} not written", path));
+                }
+            },
+            Some(Prompt::Search(mut prompt)) => match _type_in(&mut prompt.text, key) {
+                PromptKey::Accept => self._search(prompt),
+                PromptKey::Cancel => {},
+                PromptKey::Typed => {
+                    if key == KeyEvent::Ctrl(Key::Char('r')) {
+                        prompt.regex = !prompt.regex;
+                    }
+                    self.prompt = Some(Prompt::Search(prompt));
+                },
+            },
+            Some(Prompt::Filter(column, mut text)) => match _type_in(&mut text, key) {
+                PromptKey::Accept => self._filter(column, &text.iter().collect::<String>()),
+                PromptKey::Cancel => {},
+                PromptKey::Typed => self.prompt = Some(Prompt::Filter(column, text)),
+            },
+            Some(Prompt::Export(mut format, mut text)) => match _type_in(&mut text, key) {
+                PromptKey::Accept => self._export(&text.iter().collect::<String>(), format, false),
+                PromptKey::Cancel => {},
+                PromptKey::Typed => {
+                    if key == KeyEvent::Key(Key::Char('\t')) {
+                        let i = Format::ALL.iter().position(|&f| f == format).unwrap();
+                        format = Format::ALL[(i + 1) % Format::ALL.len()];
+                    } else if let KeyEvent::Key(Key::Char(_)) = key {
+                        // A known extension chooses the format as the name is typed
+                        if let Some(f) = Format::from_path(&text.iter().collect::<String>()) {
+                            format = f;
+                        }
+                    }
+                    self.prompt = Some(Prompt::Export(format, text));
+                },
+            },
+        }
+    }
+
+    /// Writes the rows shown, sorted and filtered, to `path`, asks first if it exists
+    fn _export(&mut self, path: &str, format: Format, overwrite: bool) {
+        let path = path.trim();
+        if path.is_empty() {
+            return;
+        }
+        match export::to_file(path, self.result, &self.order, format, overwrite) {
+            Ok(true) => self.message = Some(format!("Wrote {} rows as {} to {}", self.order.len(), format.name(), path)),
+            Ok(false) => self.prompt = Some(Prompt::Overwrite(format, path.to_string())),
+            Err(e) => self.message = Some(e),
+        }
+    }
+
+    /// The row of the cursor
+    fn _yank_row(&mut self, format: Format) {
+        if self.data.is_empty() {
+            return;
+        }
+        let (row, column) = self._cursor();
+        self._select(row, column);
+        self._yank(&export::format_row(self.result, self.order[row], format), "row");
+    }
+
+    /// The rows shown, sorted and filtered
+    fn _yank_result(&mut self, format: Format) {
+        let text = export::format(self.result, &self.order, format);
+        let count = self.order.len();
+        self._yank(&text, &format!("{} row{}", count, if count == 1 { "" } else { "s" }));
+    }
+
+    fn _yank(&mut self, text: &str, what: &str) {
+        clipboard::copy(text);
+        self.message = Some(format!("Copied {} to the clipboard ({} bytes)", what, text.len()));
+    }
+
+    /// Replaces the filter of `column`, an empty text removes it
+    fn _filter(&mut self, column: usize, text: &str) {
+        let filter = if text.trim().is_empty() {
+            None
+        } else {
+            match Filter::parse(text) {
+                Ok(filter) => Some(filter),
+                Err(e) => {
+                    self.message = Some(format!("Invalid filter : {}", e));
+                    return;
+                },
+            }
+        };
+        self.filters.retain(|(c, _)| *c != column);
+        self.filters.extend(filter.map(|f| (column, f)));
+        self._arrange();
+    }
+
+    /// Shows the rows of the result through the sort and the filters, the selected cell stays on its row if it is still shown
+    fn _arrange(&mut self) {
+        let selected = self.selected.map(|(row, column)| (self.order[row], column));
+        self.order = rows::arrange(self.result, &self.sort, &self.filters);
+        self.data = self.order.iter().map(|&i| self.lines[i].clone()).collect();
+        // The matches are positions in the rows shown
+        self.search = None;
+        self.selected = None;
+        self._scroll_to(self.y);
+        if let Some((row, column)) = selected.and_then(|(i, column)| self.order.iter().position(|&j| j == i).map(|row| (row, column))) {
+            self._select(row, column);
+        }
+    }
+
+    fn _sort_description(&self) -> String {
+        let keys: Vec<String> = self.sort.iter().map(|key| {
+            let name = &self.result.columns[key.column].name;
+            if key.descending { format!("{} desc", name) } else { name.clone() }
+        }).collect();
+        format!("Sorted by {}", keys.join(", "))
+    }
+
+    /// Finds every match in the rows and goes to the first one after the top of the screen.
+    /// An empty pattern searches the last one again, in the new direction.
+    fn _search(&mut self, prompt: SearchPrompt) {
+        if prompt.text.is_empty() {
+            if let Some(search) = &mut self.search {
+                search.prompt.backward = prompt.backward;
+            }
+            self._next_match(false);
+            return;
+        }
+        let text: String = prompt.text.iter().collect();
+        let regex = if prompt.regex {
+            match Regex::new(&text) {
+                Ok(r) => r,
+                Err(e) => {
+                    self.message = Some(format!("Invalid regex : {}", e));
+                    return;
+                }
+            }
+        } else {
+            Regex::literal(&text)
+        };
+
+        let mut matches = Vec::new();
+        for (row, string) in self.data.iter().enumerate() {
+            matches.extend(regex.find_all(string).into_iter().map(|(start, end)| (row, start, end)));
+        }
+        let backward = prompt.backward;
+        let current = if backward {
+            let bottom = self.y + self._height();
+            matches.iter().rposition(|m| m.0 < bottom).or(matches.len().checked_sub(1))
+        } else {
+            matches.iter().position(|m| m.0 >= self.y).or_else(|| (!matches.is_empty()).then_some(0))
+        };
+        self.search = Some(Search { prompt, matches, current });
+        self._show_current_match();
+    }
+
+    /// Next match in the direction of the search, or the other way with `reverse`, wrapping around
+    fn _next_match(&mut self, reverse: bool) {
+        if let Some(search) = &mut self.search {
+            let count = search.matches.len();
+            if let Some(current) = search.current {
+                search.current = Some(if search.prompt.backward != reverse {
+                    (current + count - 1) % count
+                } else {
+                    (current + 1) % count
+                });
+            }
+        }
+        self._show_current_match();
+    }
+
+    /// Scrolls to the current match if it is not on the screen, and tells the match count
+    fn _show_current_match(&mut self) {
+        let (row, start, end) = match &self.search {
+            Some(Search { current: Some(i), matches, .. }) => matches[*i],
+            Some(search) => {
+                self.message = Some(format!("Pattern not found : {}", search.prompt.text.iter().collect::<String>()));
+                return;
+            },
+            None => return,
+        };
+        let height = self._height();
+        if row < self.y || row >= self.y + height {
+            self._scroll_to(row.saturating_sub(height / 2));
+        }
+        let width = self.max_x as usize;
+        let pinned = self._pinned_width();
+        if end > pinned && (start < pinned + self.x || end > self.x + width) {
+            self.x = start.saturating_sub(pinned).saturating_sub(width.saturating_sub(pinned) / 4);
+        }
+    }
+
+    /// Highlights of the characters of a row, from the search and the selected cell
+    fn _highlight(&self, row: usize, position: usize) -> Option<Highlight> {
+        if let Some(search) = &self.search {
+            let first = search.matches.partition_point(|m| m.0 < row);
+            let current = search.current.map(|i| search.matches[i]);
+            for m in search.matches[first..].iter().take_while(|m| m.0 == row) {
+                if m.1 <= position && position < m.2 {
+                    return Some(if Some(*m) == current { Highlight::CurrentMatch } else { Highlight::Match });
+                }
+            }
+        }
+        // Before the separator of its column
+        let column = self.bounds.partition_point(|b| b.1 < position);
+        let (start, end) = *self.bounds.get(column)?;
+        if position < start || position == end {
+            None
+        } else if self.selected == Some((row, column)) {
+            Some(Highlight::Selected)
+        } else if self.changed.contains(&(self.order[row], column)) {
+            Some(Highlight::Changed)
+        } else {
+            None
+        }
+    }
+
+    /// `row` is `None` for the header
+    fn _render_line(&self, row: Option<usize>, string: &str) -> String {
+        let mut res = String::new();
+        let mut current = None;
+        for (offset, visible) in self._get_column_slices(string) {
+            for (i, c) in visible.char_indices() {
+                let highlight = row.and_then(|row| self._highlight(row, offset + i));
+                if highlight != current {
+                    if current.is_some() {
+                        res.push_str(Reset.as_ref());
+                    }
+                    if let Some(h) = highlight {
+                        res.push_str(&h.style());
+                    }
+                    current = highlight;
+                }
+                res.push(c);
+            }
+        }
+        if current.is_some() {
+            res.push_str(Reset.as_ref());
+        }
+        res
+    }
+
+    fn _display_status(&self) {
+        let status = if let Some(prompt) = &self.prompt {
+            match prompt {
+                Prompt::Search(prompt) => prompt.description(),
+                Prompt::Filter(column, text) => format!("filter {} : {}", self.result.columns[*column].name, text.iter().collect::<String>()),
+                Prompt::Export(format, text) => format!("export {} (Tab to change) : {}", format.name(), text.iter().collect::<String>()),
+                Prompt::Overwrite(_, path) => format!("{} exists, overwrite it ? [y/N]", path),
+            }
+        } else if let Some(message) = &self.message {
+            message.clone()
+        } else if let Some(search) = &self.search {
+            match search.current {
+                Some(i) => format!("{}  match {} of {}", search.prompt.description(), i + 1, search.matches.len()),
+                None => search.prompt.description(),
+            }
+        } else if let Some(count) = self.count {
+            count.to_string()
+        } else if let Some((_, column)) = self.selected {
+            let column = &self.result.columns[column];
+            format!("{} ({})", column.name, column.type_.name())
+        } else {
+            String::new()
+        };
+        let rows = self._row_range();
+        let width = self.max_x as usize;
+        let status: String = status.chars().take(width.saturating_sub(rows.len() + 1)).collect();
+        let padding = width.saturating_sub(status.chars().count() + rows.len());
+        print!("{}{}{}{}{}{}{}", termion::cursor::Goto(1, self.max_y), termion::clear::CurrentLine,
+               Invert, status, " ".repeat(padding), rows, Reset);
+        if self.prompt.is_some() {
+            print!("{}", termion::cursor::Goto(min(status.chars().count() as u16 + 1, self.max_x), self.max_y));
+        }
+    }
+
+    /// Rows on the screen and the row count, with the count before filtering, for the status line
+    fn _row_range(&self) -> String {
+        let total = self.data.len();
+        let first = self.y + 1;
+        let last = min(self.y + self._height(), total);
+        let filtered = if self.filters.is_empty() { String::new() } else { format!(" (of {})", self.lines.len()) };
+        if first > last {
+            format!("{} rows{}", total, filtered)
+        } else {
+            format!("rows {}-{} of {}{}", first, last, total, filtered)
+        }
+    }
+
     fn _get_row_slice(&self) -> &[String] {
-        &self.data[self.y..min(self.max_y as usize + self.y, self.data.len())]
+        &self.data[self.y..min(self._height() + self.y, self.data.len())]
     }
 
-    fn _get_column_slice(&self, columns: &'a String) -> &'a str {
-        columns.get(self.x..min(self.max_x as usize + self.x, columns.len())).unwrap()
+    /// Parts of the line on the screen, the frozen columns then the scrolled ones, with their position in the line.
+    /// Limits falling inside a character are moved before it.
+    fn _get_column_slices<'b>(&self, columns: &'b str) -> Vec<(usize, &'b str)> {
+        let boundary = |mut i: usize| {
+            i = min(i, columns.len());
+            while !columns.is_char_boundary(i) {
+                i -= 1;
+            }
+            i
+        };
+        let width = self.max_x as usize;
+        let pinned = min(self._pinned_width(), width);
+        let start = boundary(pinned + self.x);
+        vec![
+            (0, &columns[..boundary(pinned)]),
+            (start, &columns[start..max(start, boundary(self.x + width))]),
+        ]
     }
 
     fn _get_current_slice_max_len(&self) -> usize {
@@ -110,4 +763,123 @@ impl<'a> TextView<'a> {
         }
         max_len
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use postgres::types::Type;
+    use crate::sql::ResultColumn;
+
+    /// Four text columns of 8 characters, the cells are `r<row>c<column>`
+    fn _grid(rows: usize) -> QueryResult {
+        QueryResult {
+            columns: (0..4).map(|c| ResultColumn { name: format!("c{}", c), max_size: 8, type_: Type::TEXT }).collect(),
+            rows: (0..rows).map(|r| (0..4).map(|c| Some(format!("r{}c{}", r, c))).collect()).collect(),
+        }
+    }
+
+    fn _keys(tv: &mut TextView, keys: &str) {
+        for c in keys.chars() {
+            tv.handle_event(TrueEvent::KeyEvent(KeyEvent::Key(Key::Char(c))));
+        }
+    }
+
+    #[test]
+    fn go_to_row() {
+        let result = QueryResult {
+            columns: vec![ResultColumn { name: String::from("n"), max_size: 2, type_: Type::INT4 }],
+            rows: (1..=20).map(|n| vec![Some(n.to_string())]).collect(),
+        };
+        let keymap = Keymap::new();
+        let mut tv = TextView::_new(&result, &keymap, 0, (40, 10), None);
+        let top = |tv: &TextView| tv._get_row_slice()[0].trim_end_matches([' ', '|']).trim().to_string();
+
+        _keys(&mut tv, "5g");
+        assert_eq!(top(&tv), "5");
+        _keys(&mut tv, "G1G");
+        assert_eq!(top(&tv), "1");
+        _keys(&mut tv, "50g");
+        assert_eq!(tv.y + tv._height(), result.rows.len());
+    }
+
+    #[test]
+    fn column_jumps_with_frozen_columns() {
+        let result = _grid(20);
+        let keymap = Keymap::new();
+        // Columns of 10 characters and their separator, the first one stays in place
+        let mut tv = TextView::_new(&result, &keymap, 1, (25, 10), None);
+        assert_eq!(tv._pinned_width(), 11);
+
+        _keys(&mut tv, "w");
+        assert_eq!(tv.x, 11);
+        // The last column can only be shown up to the end of the lines
+        _keys(&mut tv, "w");
+        assert_eq!(tv.x, 44 - 25);
+        _keys(&mut tv, "b");
+        assert_eq!(tv.x, 11);
+        _keys(&mut tv, "b");
+        assert_eq!(tv.x, 0);
+        _keys(&mut tv, "b");
+        assert_eq!(tv.x, 0);
+    }
+
+    #[test]
+    fn select_with_horizontal_offset() {
+        let result = _grid(20);
+        let keymap = Keymap::new();
+        let mut tv = TextView::_new(&result, &keymap, 0, (25, 10), None);
+
+        _keys(&mut tv, "w");
+        // The first column of the screen is the second one of the result, on the second row under the header
+        tv._select_at(1, 4);
+        assert_eq!(tv.selected, Some((1, 1)));
+        // Clicks on the header do nothing
+        tv._select_at(1, 1);
+        assert_eq!(tv.selected, Some((1, 1)));
+
+        let mut tv = TextView::_new(&result, &keymap, 1, (25, 10), None);
+        _keys(&mut tv, "w");
+        tv._select_at(1, 3);
+        assert_eq!(tv.selected, Some((0, 0)));
+        tv._select_at(12, 3);
+        assert_eq!(tv.selected, Some((0, 2)));
+    }
+
+    #[test]
+    fn search_matches() {
+        let result = _grid(20);
+        let keymap = Keymap::new();
+        let mut tv = TextView::_new(&result, &keymap, 0, (80, 10), None);
+
+        _keys(&mut tv, "/c3\n");
+        assert_eq!(tv.search.as_ref().unwrap().matches.len(), 20);
+        _keys(&mut tv, "/1c\n");
+        let search = tv.search.as_ref().unwrap();
+        // r1c0 to r1c3 and r11c0 to r11c3
+        assert_eq!(search.matches.len(), 8);
+        assert_eq!(search.current, Some(0));
+        _keys(&mut tv, "nn");
+        assert_eq!(tv.search.as_ref().unwrap().current, Some(2));
+        _keys(&mut tv, "N");
+        assert_eq!(tv.search.as_ref().unwrap().current, Some(1));
+        _keys(&mut tv, "/nothing\n");
+        assert!(tv.search.as_ref().unwrap().matches.is_empty());
+    }
+
+    #[test]
+    fn changed_cells_follow_the_sort() {
+        let result = _grid(20);
+        let keymap = Keymap::new();
+        let mut tv = TextView::_new(&result, &keymap, 0, (80, 10), None);
+        tv.mark_changed(vec![(0, 1)].into_iter().collect());
+        let changed = |tv: &TextView, row| tv._highlight(row, tv.bounds[1].0 + 1) == Some(Highlight::Changed);
+        assert!(changed(&tv, 0));
+
+        // Descending on the first column, "r0c0" comes last
+        _keys(&mut tv, "ss");
+        assert_eq!(tv.order[19], 0);
+        assert!(!changed(&tv, 0));
+        assert!(changed(&tv, 19));
+    }
+}
@@ -0,0 +1,80 @@
+const UNDO_MAX: usize = 100;
+
+/// Buffer and cursor position before an edit
+type Snapshot = (Vec<char>, usize);
+
+pub struct UndoStack {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+impl UndoStack {
+    pub fn new() -> UndoStack {
+        UndoStack {
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Records the state before an edit. With `merge`, the edit joins the previous group instead
+    pub fn save(&mut self, buffer: &[char], index: usize, merge: bool) {
+        self.redo.clear();
+        if merge && !self.undo.is_empty() {
+            return;
+        }
+        if self.undo.len() == UNDO_MAX {
+            self.undo.remove(0);
+        }
+        self.undo.push((buffer.to_vec(), index));
+    }
+
+    /// Takes the current state and returns the one to restore
+    pub fn undo(&mut self, buffer: &[char], index: usize) -> Option<Snapshot> {
+        let snapshot = self.undo.pop()?;
+        self.redo.push((buffer.to_vec(), index));
+        Some(snapshot)
+    }
+
+    pub fn redo(&mut self, buffer: &[char], index: usize) -> Option<Snapshot> {
+        let snapshot = self.redo.pop()?;
+        self.undo.push((buffer.to_vec(), index));
+        Some(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut stack = UndoStack::new();
+        // Typing "sel", then " *" as another group
+        stack.save(&_chars(""), 0, false);
+        stack.save(&_chars("s"), 1, true);
+        stack.save(&_chars("se"), 2, true);
+        stack.save(&_chars("sel"), 3, false);
+        stack.save(&_chars("sel "), 4, true);
+
+        assert_eq!(stack.undo(&_chars("sel *"), 5), Some((_chars("sel"), 3)));
+        assert_eq!(stack.undo(&_chars("sel"), 3), Some((_chars(""), 0)));
+        assert_eq!(stack.undo(&_chars(""), 0), None);
+        assert_eq!(stack.redo(&_chars(""), 0), Some((_chars("sel"), 3)));
+        assert_eq!(stack.redo(&_chars("sel"), 3), Some((_chars("sel *"), 5)));
+        assert_eq!(stack.redo(&_chars("sel *"), 5), None);
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut stack = UndoStack::new();
+        stack.save(&_chars(""), 0, false);
+        stack.undo(&_chars("a"), 1);
+        stack.save(&_chars(""), 0, false);
+
+        assert_eq!(stack.redo(&_chars("b"), 1), None);
+    }
+}
@@ -0,0 +1,369 @@
+use std::cmp::min;
+use crate::lexer;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ViMode {
+    Insert,
+    Normal,
+    Visual,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Motion {
+    Left,
+    Right,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    FindForward(char),
+    TillForward(char),
+    FindBackward(char),
+    TillBackward(char),
+    /// `%`, the bracket or quote matching the next one from the cursor
+    Matching,
+    /// `dd`, `cc`, `yy`
+    WholeLine,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum InsertPosition {
+    BeforeCursor,
+    AfterCursor,
+    LineStart,
+    LineEnd,
+}
+
+/// What the keys typed so far in normal or visual mode resolve to
+#[derive(PartialEq, Debug)]
+pub enum ViCommand {
+    Pending,
+    Move(Motion, usize),
+    Operate(Operator, Motion, usize),
+    OperateSelection(Operator),
+    Put { before: bool, count: usize },
+    Insert(InsertPosition),
+    ToggleVisual,
+    Undo(usize),
+    HistoryPrev,
+    HistoryNext,
+    Invalid,
+}
+
+pub struct ViState {
+    pub mode: ViMode,
+    pub visual_start: usize,
+    count: Option<usize>,
+    operator: Option<(Operator, usize)>,
+    find: Option<char>,
+}
+
+impl ViState {
+    pub fn new() -> ViState {
+        ViState {
+            mode: ViMode::Insert,
+            visual_start: 0,
+            count: None,
+            operator: None,
+            find: None,
+        }
+    }
+
+    /// Shown before the prompt, like readline's `show-mode-in-prompt`
+    pub fn mode_indicator(&self) -> &'static str {
+        match self.mode {
+            ViMode::Insert => "(ins) ",
+            ViMode::Normal => "(cmd) ",
+            ViMode::Visual => "(vis) ",
+        }
+    }
+
+    /// Forgets any half typed command
+    pub fn reset(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.find = None;
+    }
+
+    pub fn key(&mut self, c: char) -> ViCommand {
+        if let Some(f) = self.find.take() {
+            let motion = match f {
+                'f' => Motion::FindForward(c),
+                't' => Motion::TillForward(c),
+                'F' => Motion::FindBackward(c),
+                _ => Motion::TillBackward(c),
+            };
+            return self._motion(motion);
+        }
+
+        match c {
+            '1'..='9' | '0' if c != '0' || self.count.is_some() => {
+                self.count = Some(self.count.unwrap_or(0) * 10 + c.to_digit(10).unwrap() as usize);
+                ViCommand::Pending
+            },
+            'h' => self._motion(Motion::Left),
+            'l' | ' ' => self._motion(Motion::Right),
+            'w' => self._motion(Motion::WordForward),
+            'b' => self._motion(Motion::WordBackward),
+            'e' => self._motion(Motion::WordEnd),
+            '0' | '^' => self._motion(Motion::LineStart),
+            '$' => self._motion(Motion::LineEnd),
+            '%' => self._motion(Motion::Matching),
+            'f' | 't' | 'F' | 'T' => {
+                self.find = Some(c);
+                ViCommand::Pending
+            },
+            'd' | 'c' | 'y' => {
+                let operator = match c {
+                    'd' => Operator::Delete,
+                    'c' => Operator::Change,
+                    _ => Operator::Yank,
+                };
+                if self.mode == ViMode::Visual {
+                    return self._done(ViCommand::OperateSelection(operator));
+                }
+                match self.operator {
+                    None => {
+                        self.operator = Some((operator, self.count.take().unwrap_or(1)));
+                        ViCommand::Pending
+                    },
+                    Some((o, _)) if o == operator => self._motion(Motion::WholeLine),
+                    Some(_) => self._done(ViCommand::Invalid),
+                }
+            },
+            _ if self.operator.is_some() => self._done(ViCommand::Invalid),
+            'x' if self.mode == ViMode::Visual => self._done(ViCommand::OperateSelection(Operator::Delete)),
+            'x' => self._operate(Operator::Delete, Motion::Right),
+            'X' => self._operate(Operator::Delete, Motion::Left),
+            'D' => self._operate(Operator::Delete, Motion::LineEnd),
+            'C' => self._operate(Operator::Change, Motion::LineEnd),
+            'p' | 'P' => {
+                let count = self.count.unwrap_or(1);
+                self._done(ViCommand::Put { before: c == 'P', count })
+            },
+            'i' => self._done(ViCommand::Insert(InsertPosition::BeforeCursor)),
+            'a' => self._done(ViCommand::Insert(InsertPosition::AfterCursor)),
+            'I' => self._done(ViCommand::Insert(InsertPosition::LineStart)),
+            'A' => self._done(ViCommand::Insert(InsertPosition::LineEnd)),
+            'v' => self._done(ViCommand::ToggleVisual),
+            'u' => {
+                let count = self.count.unwrap_or(1);
+                self._done(ViCommand::Undo(count))
+            },
+            'k' => self._done(ViCommand::HistoryPrev),
+            'j' => self._done(ViCommand::HistoryNext),
+            _ => self._done(ViCommand::Invalid),
+        }
+    }
+
+    fn _motion(&mut self, motion: Motion) -> ViCommand {
+        let count = self.count.take().unwrap_or(1);
+        match self.operator.take() {
+            Some((operator, n)) => ViCommand::Operate(operator, motion, n * count),
+            None => ViCommand::Move(motion, count),
+        }
+    }
+
+    fn _operate(&mut self, operator: Operator, motion: Motion) -> ViCommand {
+        let count = self.count.take().unwrap_or(1);
+        ViCommand::Operate(operator, motion, count)
+    }
+
+    fn _done(&mut self, command: ViCommand) -> ViCommand {
+        self.reset();
+        command
+    }
+}
+
+#[derive(PartialEq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn _class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+fn _word_forward(buffer: &[char], mut i: usize) -> usize {
+    if i < buffer.len() && _class(buffer[i]) != CharClass::Blank {
+        let class = _class(buffer[i]);
+        while i < buffer.len() && _class(buffer[i]) == class {
+            i += 1;
+        }
+    }
+    while i < buffer.len() && _class(buffer[i]) == CharClass::Blank {
+        i += 1;
+    }
+    i
+}
+
+fn _word_backward(buffer: &[char], mut i: usize) -> usize {
+    while i > 0 && _class(buffer[i - 1]) == CharClass::Blank {
+        i -= 1;
+    }
+    if i > 0 {
+        let class = _class(buffer[i - 1]);
+        while i > 0 && _class(buffer[i - 1]) == class {
+            i -= 1;
+        }
+    }
+    i
+}
+
+fn _word_end(buffer: &[char], mut i: usize) -> usize {
+    i += 1;
+    while i < buffer.len() && _class(buffer[i]) == CharClass::Blank {
+        i += 1;
+    }
+    if i < buffer.len() {
+        let class = _class(buffer[i]);
+        while i + 1 < buffer.len() && _class(buffer[i + 1]) == class {
+            i += 1;
+        }
+    }
+    min(i, buffer.len().saturating_sub(1))
+}
+
+fn _find_forward(buffer: &[char], i: usize, c: char, count: usize) -> Option<usize> {
+    buffer.iter().enumerate().skip(i + 1).filter(|&(_, &b)| b == c).map(|(j, _)| j).nth(count - 1)
+}
+
+fn _find_backward(buffer: &[char], i: usize, c: char, count: usize) -> Option<usize> {
+    buffer[..min(i, buffer.len())].iter().rposition(|&b| b == c).and_then(|j| {
+        if count > 1 { _find_backward(buffer, j, c, count - 1) } else { Some(j) }
+    })
+}
+
+/// Where `motion` repeated `count` times moves the cursor, `None` if it cannot be done
+pub fn motion_target(buffer: &[char], index: usize, motion: &Motion, count: usize) -> Option<usize> {
+    let repeat = |f: fn(&[char], usize) -> usize| (0..count).fold(index, |i, _| f(buffer, i));
+    match motion {
+        Motion::Left => Some(index.saturating_sub(count)),
+        Motion::Right => Some(min(index + count, buffer.len())),
+        Motion::WordForward => Some(repeat(_word_forward)),
+        Motion::WordBackward => Some(repeat(_word_backward)),
+        Motion::WordEnd => Some(repeat(_word_end)),
+        Motion::LineStart => Some(0),
+        Motion::LineEnd | Motion::WholeLine => Some(buffer.len()),
+        Motion::FindForward(c) => _find_forward(buffer, index, *c, count),
+        Motion::TillForward(c) => _find_forward(buffer, index, *c, count).map(|j| j - 1),
+        Motion::FindBackward(c) => _find_backward(buffer, index, *c, count),
+        Motion::TillBackward(c) => _find_backward(buffer, index, *c, count).map(|j| j + 1),
+        Motion::Matching => _matching_pair(buffer, index).map(|(_, j)| j),
+    }
+}
+
+/// The first bracket or quote at or after `index` that has a match, and the position of that match
+fn _matching_pair(buffer: &[char], index: usize) -> Option<(usize, usize)> {
+    let pairs = lexer::pairs(buffer);
+    (index..buffer.len()).find_map(|i| pairs.matching(i).map(|j| (i, j)))
+}
+
+/// The `[start, end)` range `operator` applies to when combined with `motion`
+pub fn motion_range(buffer: &[char], index: usize, operator: Operator, motion: &Motion, count: usize) -> Option<(usize, usize)> {
+    match motion {
+        Motion::WholeLine => Some((0, buffer.len())),
+        // `cw` changes up to the end of the word, like `ce`, `dw` and `yw` include the blanks after it
+        Motion::WordForward if operator == Operator::Change && index < buffer.len() && _class(buffer[index]) != CharClass::Blank => {
+            motion_range(buffer, index, operator, &Motion::WordEnd, count)
+        },
+        Motion::WordEnd | Motion::FindForward(_) => {
+            motion_target(buffer, index, motion, count).map(|j| (index, min(j + 1, buffer.len())))
+        },
+        // Like in vim, `dt)` right before the `)` has nothing to act on
+        Motion::TillForward(_) => {
+            motion_target(buffer, index, motion, count).filter(|&j| j > index).map(|j| (index, j + 1))
+        },
+        // Both brackets are included
+        Motion::Matching => {
+            _matching_pair(buffer, index).map(|(i, j)| (min(i, j), std::cmp::max(i, j) + 1))
+        },
+        _ => {
+            motion_target(buffer, index, motion, count).map(|j| if j < index { (j, index) } else { (index, j) })
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn word_motions() {
+        let buffer = _chars("select a.id, b from t");
+
+        assert_eq!(motion_target(&buffer, 0, &Motion::WordForward, 1), Some(7));
+        assert_eq!(motion_target(&buffer, 7, &Motion::WordForward, 1), Some(8));
+        assert_eq!(motion_target(&buffer, 7, &Motion::WordForward, 3), Some(11));
+        assert_eq!(motion_target(&buffer, 11, &Motion::WordBackward, 1), Some(9));
+        assert_eq!(motion_target(&buffer, 0, &Motion::WordEnd, 1), Some(5));
+        assert_eq!(motion_target(&buffer, 20, &Motion::WordForward, 1), Some(21));
+    }
+
+    #[test]
+    fn find_motions() {
+        let buffer = _chars("a(b(c))");
+
+        assert_eq!(motion_target(&buffer, 0, &Motion::FindForward('('), 2), Some(3));
+        assert_eq!(motion_target(&buffer, 0, &Motion::TillForward(')'), 1), Some(4));
+        assert_eq!(motion_target(&buffer, 6, &Motion::FindBackward('('), 1), Some(3));
+        assert_eq!(motion_target(&buffer, 6, &Motion::TillBackward('('), 2), Some(2));
+        assert_eq!(motion_target(&buffer, 0, &Motion::FindForward('x'), 1), None);
+        assert_eq!(motion_target(&buffer, 0, &Motion::Matching, 1), Some(6));
+        assert_eq!(motion_range(&buffer, 5, Operator::Delete, &Motion::Matching, 1), Some((3, 6)));
+        // From inside the brackets, the range goes from the one found after the cursor to its match
+        assert_eq!(motion_range(&_chars("(a)"), 1, Operator::Delete, &Motion::Matching, 1), Some((0, 3)));
+    }
+
+    #[test]
+    fn operator_ranges() {
+        let buffer = _chars("select id from t");
+
+        assert_eq!(motion_range(&buffer, 0, Operator::Delete, &Motion::WordForward, 1), Some((0, 7)));
+        assert_eq!(motion_range(&buffer, 0, Operator::Yank, &Motion::WordForward, 1), Some((0, 7)));
+        assert_eq!(motion_range(&buffer, 0, Operator::Change, &Motion::WordForward, 1), Some((0, 6)));
+        assert_eq!(motion_range(&buffer, 6, Operator::Change, &Motion::WordForward, 1), Some((6, 7)));
+        assert_eq!(motion_range(&buffer, 7, Operator::Delete, &Motion::LineEnd, 1), Some((7, 16)));
+        assert_eq!(motion_range(&buffer, 7, Operator::Delete, &Motion::FindForward('m'), 1), Some((7, 14)));
+        assert_eq!(motion_range(&buffer, 7, Operator::Delete, &Motion::TillForward('m'), 1), Some((7, 13)));
+        assert_eq!(motion_range(&buffer, 8, Operator::Delete, &Motion::TillForward(' '), 1), None);
+        assert_eq!(motion_range(&buffer, 7, Operator::Delete, &Motion::WordBackward, 1), Some((0, 7)));
+    }
+
+    #[test]
+    fn counts_and_operators() {
+        let mut vi = ViState::new();
+        vi.mode = ViMode::Normal;
+
+        assert_eq!(vi.key('2'), ViCommand::Pending);
+        assert_eq!(vi.key('d'), ViCommand::Pending);
+        assert_eq!(vi.key('3'), ViCommand::Pending);
+        assert_eq!(vi.key('w'), ViCommand::Operate(Operator::Delete, Motion::WordForward, 6));
+        assert_eq!(vi.key('c'), ViCommand::Pending);
+        assert_eq!(vi.key('c'), ViCommand::Operate(Operator::Change, Motion::WholeLine, 1));
+        assert_eq!(vi.key('y'), ViCommand::Pending);
+        assert_eq!(vi.key('t'), ViCommand::Pending);
+        assert_eq!(vi.key('w'), ViCommand::Operate(Operator::Yank, Motion::TillForward('w'), 1));
+        assert_eq!(vi.key('0'), ViCommand::Move(Motion::LineStart, 1));
+    }
+}